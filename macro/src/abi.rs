@@ -0,0 +1,134 @@
+use quote::quote;
+
+use crate::{doc_filter, naming::Mangler};
+
+/// FNV-1a, so hashes do not depend on the compiler version or platform.
+fn fnv1a(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Removes `#[doc = "..."]` attributes from `tokens`.
+fn strip_docs(tokens: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+  let mut stripped = Vec::new();
+
+  for token in tokens {
+    if let proc_macro2::TokenTree::Group(group) = &token {
+      let is_doc = group.delimiter() == proc_macro2::Delimiter::Bracket
+        && matches!(group.stream().into_iter().next(), Some(proc_macro2::TokenTree::Ident(ident)) if ident == "doc");
+      let after_pound = matches!(stripped.last(), Some(proc_macro2::TokenTree::Punct(punct)) if punct.as_char() == '#');
+
+      if is_doc && after_pound {
+        stripped.pop();
+        continue;
+      }
+    }
+
+    stripped.push(token);
+  }
+
+  stripped.into_iter().collect()
+}
+
+/// Appends `tokens` to `text` as identifiers, punctuation, literals and delimiters
+/// separated by spaces, as `TokenStream::to_string` may change between compiler versions.
+fn canonical(tokens: proc_macro2::TokenStream, text: &mut String) {
+  for token in tokens {
+    match token {
+      proc_macro2::TokenTree::Group(group) => {
+        let (open, close) = match group.delimiter() {
+          proc_macro2::Delimiter::Parenthesis => ("(", ")"),
+          proc_macro2::Delimiter::Brace => ("{", "}"),
+          proc_macro2::Delimiter::Bracket => ("[", "]"),
+          proc_macro2::Delimiter::None => ("\u{2}", "\u{3}"),
+        };
+
+        text.push_str(open);
+        text.push(' ');
+        canonical(group.stream(), text);
+        text.push_str(close);
+      },
+      proc_macro2::TokenTree::Ident(ident) => text.push_str(&ident.to_string()),
+      proc_macro2::TokenTree::Punct(punct) => text.push(punct.as_char()),
+      proc_macro2::TokenTree::Literal(literal) => text.push_str(&literal.to_string()),
+    }
+
+    text.push(' ');
+  }
+}
+
+/// Hashes the signatures and export attributes of the generated `items`,
+/// ignoring function bodies and doc comments so only changes to the ABI affect it.
+pub fn interface_hash(items: &[proc_macro2::TokenStream]) -> u64 {
+  let mut text = String::new();
+
+  for item in items {
+    if let Ok(method) = syn::parse2::<syn::ImplItemMethod>(item.clone()) {
+      let attrs = method.attrs.iter().filter(|attr| !doc_filter(attr));
+      let sig = &method.sig;
+      canonical(quote!(#(#attrs)* #sig), &mut text);
+    } else {
+      canonical(strip_docs(item.clone()), &mut text);
+    }
+
+    text.push('\n');
+  }
+
+  fnv1a(text.as_bytes())
+}
+
+/// Exports the interface hash of `items` as `___interface___{hash}`.
+///
+/// The hash is part of the symbol name, as an item may have several exported impl
+/// blocks. Bindings record these symbols when they are generated, and check that
+/// they still exist when loading the library.
+pub fn make_interface_hash(mangler: &Mangler, items: &[proc_macro2::TokenStream]) -> proc_macro2::TokenStream {
+  let hash = interface_hash(items);
+  let (hash_name, hash_export) = mangler.symbol(&["interface", &format!("{:016x}", hash)]);
+
+  quote!(
+    #hash_export
+    #[allow(non_upper_case_globals)]
+    pub static #hash_name: u64 = #hash;
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use quote::quote;
+
+  use super::interface_hash;
+
+  #[test]
+  fn bodies_and_docs_are_ignored() {
+    let hash = interface_hash(&[quote!(
+      #[no_mangle]
+      pub unsafe extern "C" fn size(this: *const u8) -> u32 { 1 }
+    )]);
+
+    let changed = interface_hash(&[quote!(
+      /// The size.
+      #[no_mangle]
+      pub unsafe extern "C" fn size(this: *const u8) -> u32 { 2 }
+    )]);
+
+    assert_eq!(hash, changed);
+  }
+
+  #[test]
+  fn signatures_change_the_hash() {
+    let hash = interface_hash(&[quote!(pub unsafe extern "C" fn size(this: *const u8) -> u32 { 1 })]);
+
+    assert_ne!(hash, interface_hash(&[quote!(pub unsafe extern "C" fn size(this: *const u8) -> u64 { 1 })]));
+    assert_ne!(hash, interface_hash(&[quote!(pub unsafe extern "C" fn size(this: *const u8, scale: u32) -> u32 { 1 })]));
+    assert_ne!(hash, interface_hash(&[quote!(#[no_mangle] pub unsafe extern "C" fn size(this: *const u8) -> u32 { 1 })]));
+  }
+
+  #[test]
+  fn other_items_change_the_hash() {
+    let hash = interface_hash(&[quote!(::finch_gen::builtin::class::Boxed)]);
+
+    assert_eq!(hash, interface_hash(&[quote!(/// Boxed.
+      ::finch_gen::builtin::class::Boxed)]));
+    assert_ne!(hash, interface_hash(&[quote!(::finch_gen::builtin::class::Shared)]));
+  }
+}
@@ -0,0 +1,256 @@
+use quote::quote;
+use syn::spanned::Spanned;
+
+use crate::diagnostic::{Diagnostic, DiagnosticLevel};
+use crate::naming::Scheme;
+
+/// Options passed as arguments to `#[finch_bindgen(...)]`.
+#[derive(Default)]
+pub struct Options {
+  /// Pass the struct by value as a `#[repr(C)]` type instead of as an opaque class.
+  pub value: bool,
+  /// How objects of a class are owned, from `#[finch_bindgen(checked)]`, `#[finch_bindgen(handle)]`,
+  /// `#[finch_bindgen(shared)]`, `#[finch_bindgen(shared_mutex)]`, `#[finch_bindgen(mutex)]`
+  /// or `#[finch_bindgen(thread_bound)]`.
+  pub ownership: Option<Ownership>,
+  /// Assert that a class is `Send`, so the host can move it between threads.
+  pub send: bool,
+  /// Assert that a class is `Sync`, so the host can call it from several threads at once.
+  pub sync: bool,
+  /// Expose `Clone` to the host, detected automatically from `#[derive(Clone)]`.
+  pub clone: bool,
+  /// Expose `Debug` to the host, detected automatically from `#[derive(Debug)]`.
+  pub debug: bool,
+  /// Expose `Display` to the host.
+  pub display: bool,
+  /// Expose `PartialEq` to the host, detected automatically from `#[derive(PartialEq)]`.
+  pub eq: bool,
+  /// Expose `Hash` to the host, detected automatically from `#[derive(Hash)]`.
+  pub hash: bool,
+  /// Export a method's return value as an iterator over this item type.
+  pub iterator: Option<syn::Type>,
+  /// Export a method's return value as a stream of this item type.
+  pub stream: Option<syn::Type>,
+  /// Namespace added to the exported symbol names, such as `"net::http"`.
+  ///
+  /// Proc macros cannot see the module they are expanded in, so this must be
+  /// given explicitly to export structs of the same name from different modules.
+  pub namespace: Option<syn::Path>,
+  /// Name used in the exported symbols instead of the struct, method or field name.
+  pub name: Option<syn::Ident>,
+  /// Prefix used in the exported symbols instead of the crate name.
+  pub prefix: Option<syn::Ident>,
+  /// Scheme used to build the exported symbol names.
+  pub naming: Option<Scheme>,
+  /// How the `String`s of an impl or method are passed, from `#[finch_bindgen(string = "...")]`.
+  pub strings: Option<Strings>,
+}
+
+/// The ownership backends in `finch_gen::builtin::class`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Ownership {
+  Boxed,
+  Checked,
+  Handle,
+  Shared,
+  SharedMutex,
+  Locked,
+  ThreadBound,
+}
+
+impl Ownership {
+  pub fn parse(value: &str) -> Option<Self> {
+    match value {
+      "boxed" => Some(Self::Boxed),
+      "checked" => Some(Self::Checked),
+      "handle" => Some(Self::Handle),
+      "shared" => Some(Self::Shared),
+      "shared_mutex" => Some(Self::SharedMutex),
+      "mutex" => Some(Self::Locked),
+      "thread_bound" => Some(Self::ThreadBound),
+      _ => None,
+    }
+  }
+
+  /// The ownership of a class without an explicit option, from `FINCH_GEN_OWNERSHIP`
+  /// if set, for example by a build script with `cargo:rustc-env`.
+  pub fn crate_default() -> Result<Self, Diagnostic> {
+    match std::env::var("FINCH_GEN_OWNERSHIP") {
+      Ok(value) if !value.is_empty() => Self::parse(&value).ok_or_else(|| {
+        Diagnostic::spanned(proc_macro2::Span::call_site(), DiagnosticLevel::Error, &format!("finch-gen[E0022] invalid ownership '{}' in FINCH_GEN_OWNERSHIP", value))
+          .note("go to https://finch-gen.github.io/docs/errors/E0022 for more information")
+          .help("expected 'boxed', 'checked', 'handle', 'shared', 'shared_mutex', 'mutex' or 'thread_bound'")
+      }),
+      _ => Ok(Self::Boxed),
+    }
+  }
+}
+
+/// How `String`s are passed to and from the host.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Strings {
+  /// `FinchString`, with a length and no NUL terminator, the default.
+  Finch,
+  /// `FinchCString`, NUL-terminated, in both directions.
+  C,
+  /// `*const c_char` borrowed from the host for arguments, `FinchCString` for return values.
+  CChar,
+  /// `FinchString16`, and `FinchStr16` for `&str` arguments.
+  Utf16,
+}
+
+impl Strings {
+  pub fn parse(value: &str) -> Option<Self> {
+    match value {
+      "finch" => Some(Self::Finch),
+      "c" => Some(Self::C),
+      "c_char" => Some(Self::CChar),
+      "utf16" => Some(Self::Utf16),
+      _ => None,
+    }
+  }
+
+  /// The string mode of a method without an explicit option, from `FINCH_GEN_STRING` if set.
+  pub fn crate_default() -> Result<Self, Diagnostic> {
+    match std::env::var("FINCH_GEN_STRING") {
+      Ok(value) if !value.is_empty() => Self::parse(&value).ok_or_else(|| {
+        Diagnostic::spanned(proc_macro2::Span::call_site(), DiagnosticLevel::Error, &format!("finch-gen[E0025] invalid string mode '{}' in FINCH_GEN_STRING", value))
+          .note("go to https://finch-gen.github.io/docs/errors/E0025 for more information")
+          .help("expected 'finch', 'c', 'c_char' or 'utf16'")
+      }),
+      _ => Ok(Self::Finch),
+    }
+  }
+}
+
+impl Options {
+  pub fn parse(args: &[syn::NestedMeta]) -> Result<Self, Diagnostic> {
+    let mut options = Self::default();
+
+    for arg in args {
+      match arg {
+        syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("value") => options.value = true,
+        syn::NestedMeta::Meta(syn::Meta::Path(path)) if Ownership::parse(&path.get_ident().map(|ident| ident.to_string()).unwrap_or_default()).is_some() => {
+          if options.ownership.is_some() {
+            return Err(
+              Diagnostic::spanned(arg.span(), DiagnosticLevel::Error, &format!("finch-gen[E0013] conflicting option '{}' for #[finch_bindgen]", quote!(#arg)))
+                .note("go to https://finch-gen.github.io/docs/errors/E0013 for more information")
+            );
+          }
+
+          options.ownership = Ownership::parse(&path.get_ident().unwrap().to_string());
+        },
+        syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("send") => options.send = true,
+        syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("sync") => options.sync = true,
+        syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("clone") => options.clone = true,
+        syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("debug") => options.debug = true,
+        syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("display") => options.display = true,
+        syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("eq") => options.eq = true,
+        syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("hash") => options.hash = true,
+
+        syn::NestedMeta::Meta(syn::Meta::NameValue(value)) if value.path.is_ident("iterator") => {
+          options.iterator = Some(parse_lit(&value.lit)?);
+        },
+
+        syn::NestedMeta::Meta(syn::Meta::NameValue(value)) if value.path.is_ident("stream") => {
+          options.stream = Some(parse_lit(&value.lit)?);
+        },
+
+        syn::NestedMeta::Meta(syn::Meta::NameValue(value)) if value.path.is_ident("namespace") => {
+          let namespace: syn::Path = parse_lit(&value.lit)?;
+          if namespace.leading_colon.is_some() || namespace.segments.iter().any(|segment| !segment.arguments.is_empty()) {
+            return Err(invalid_value(&value.lit));
+          }
+
+          options.namespace = Some(namespace);
+        },
+
+        syn::NestedMeta::Meta(syn::Meta::NameValue(value)) if value.path.is_ident("name") => {
+          options.name = Some(parse_lit(&value.lit)?);
+        },
+
+        syn::NestedMeta::Meta(syn::Meta::NameValue(value)) if value.path.is_ident("prefix") => {
+          options.prefix = Some(parse_lit(&value.lit)?);
+        },
+
+        syn::NestedMeta::Meta(syn::Meta::NameValue(value)) if value.path.is_ident("naming") => {
+          options.naming = match &value.lit {
+            syn::Lit::Str(lit) => Scheme::parse(&lit.value()),
+            _ => None,
+          };
+
+          if options.naming.is_none() {
+            return Err(
+              invalid_value(&value.lit)
+                .help("expected \"finch\", \"snake\" or a template containing `{name}` and `{member}`, such as \"{prefix}_{name}_{member}\"")
+            );
+          }
+        },
+
+        syn::NestedMeta::Meta(syn::Meta::NameValue(value)) if value.path.is_ident("string") => {
+          options.strings = match &value.lit {
+            syn::Lit::Str(lit) => Strings::parse(&lit.value()),
+            _ => None,
+          };
+
+          if options.strings.is_none() {
+            return Err(
+              invalid_value(&value.lit)
+                .help("expected \"finch\", \"c\", \"c_char\" or \"utf16\"")
+            );
+          }
+        },
+
+        _ => {
+          return Err(
+            Diagnostic::spanned(arg.span(), DiagnosticLevel::Error, &format!("finch-gen[E0013] unknown option '{}' for #[finch_bindgen]", quote!(#arg)))
+              .note("go to https://finch-gen.github.io/docs/errors/E0013 for more information")
+          );
+        }
+      }
+    }
+
+    Ok(options)
+  }
+
+  /// Parses the `#[finch_bindgen(...)]` attributes placed on a method or field.
+  pub fn from_attrs(attrs: &[syn::Attribute]) -> Result<Self, Diagnostic> {
+    let mut args = Vec::new();
+
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("finch_bindgen")) {
+      match attr.parse_meta() {
+        Ok(syn::Meta::List(list)) => args.extend(list.nested),
+        Ok(syn::Meta::Path(_)) => {},
+        _ => {
+          return Err(
+            Diagnostic::spanned(attr.span(), DiagnosticLevel::Error, "finch-gen[E0013] expected #[finch_bindgen(...)]")
+              .note("go to https://finch-gen.github.io/docs/errors/E0013 for more information")
+          );
+        }
+      }
+    }
+
+    Self::parse(&args)
+  }
+}
+
+/// Removes `#[finch_bindgen(...)]` attributes from a method or field, as they
+/// are only read by the macro expanding the enclosing item.
+pub fn strip_attrs(attrs: &mut Vec<syn::Attribute>) {
+  attrs.retain(|attr| !attr.path.is_ident("finch_bindgen"));
+}
+
+fn parse_lit<T: syn::parse::Parse>(lit: &syn::Lit) -> Result<T, Diagnostic> {
+  if let syn::Lit::Str(lit) = lit {
+    if let Ok(value) = lit.parse() {
+      return Ok(value);
+    }
+  }
+
+  Err(invalid_value(lit))
+}
+
+fn invalid_value(lit: &syn::Lit) -> Diagnostic {
+  Diagnostic::spanned(lit.span(), DiagnosticLevel::Error, &format!("finch-gen[E0013] invalid value '{}' for #[finch_bindgen]", quote!(#lit)))
+    .note("go to https://finch-gen.github.io/docs/errors/E0013 for more information")
+}
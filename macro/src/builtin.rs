@@ -1,24 +1,183 @@
-use quote::{quote, format_ident};
+use quote::quote;
 
-pub fn make_builtin(crate_name: String) -> proc_macro2::TokenStream {
-  let string_new_fn_name = format_ident!("___finch_bindgen___{}___builtin___FinchString___new", crate_name);
-  let string_drop_fn_name = format_ident!("___finch_bindgen___{}___builtin___FinchString___drop", crate_name);
-  let cstring_drop_fn_name = format_ident!("___finch_bindgen___{}___builtin___FinchCString___drop", crate_name);
+use crate::attr::Options;
+use crate::diagnostic::Diagnostic;
+use crate::naming::Mangler;
 
+/// Checks that `export_builtins!()` was invoked at the crate root, so missing
+/// builtins are reported at compile time rather than when the host loads the library.
+///
+/// Names imported into the inner block shadow the outer one, so the marker defined at
+/// the crate root is found when it exists, and the failing one from finch-gen otherwise.
+pub fn assert_builtins() -> proc_macro2::TokenStream {
   quote!(
-    #[no_mangle]
+    const _: () = {
+      #[allow(unused_imports)]
+      use ::finch_gen::builtin::missing::*;
+
+      {
+        #[allow(unused_imports)]
+        use crate::*;
+
+        ::finch_gen::builtin::assert_builtins(&___finch_bindgen___export_builtins)
+      }
+    };
+  )
+}
+
+/// Exports the builtins, named with the `prefix` and `naming` options passed to `export_builtins!()`.
+pub fn make_builtin(options: &Options) -> Result<proc_macro2::TokenStream, Diagnostic> {
+  let (abi_version_name, abi_version_export) = Mangler::builtin("", options)?.symbol(&["abi_version"]);
+
+  let string = Mangler::builtin("FinchString", options)?;
+  let string16 = Mangler::builtin("FinchString16", options)?;
+  let bytes = Mangler::builtin("FinchBytes", options)?;
+  let cstring = Mangler::builtin("FinchCString", options)?;
+  let map = Mangler::builtin("FinchMap", options)?;
+  let set = Mangler::builtin("FinchSet", options)?;
+
+  let (string_new_fn_name, string_new_export) = string.symbol(&["new"]);
+  let (string_drop_fn_name, string_drop_export) = string.symbol(&["drop"]);
+  let (string16_new_fn_name, string16_new_export) = string16.symbol(&["new"]);
+  let (string16_drop_fn_name, string16_drop_export) = string16.symbol(&["drop"]);
+  let (bytes_new_fn_name, bytes_new_export) = bytes.symbol(&["new"]);
+  let (bytes_drop_fn_name, bytes_drop_export) = bytes.symbol(&["drop"]);
+  let (cstring_new_fn_name, cstring_new_export) = cstring.symbol(&["new"]);
+  let (cstring_drop_fn_name, cstring_drop_export) = cstring.symbol(&["drop"]);
+  let (map_len_fn_name, map_len_export) = map.symbol(&["len"]);
+  let (map_key_fn_name, map_key_export) = map.symbol(&["key"]);
+  let (map_value_fn_name, map_value_export) = map.symbol(&["value"]);
+  let (map_get_fn_name, map_get_export) = map.symbol(&["get"]);
+  let (map_drop_fn_name, map_drop_export) = map.symbol(&["drop"]);
+  let (set_len_fn_name, set_len_export) = set.symbol(&["len"]);
+  let (set_item_fn_name, set_item_export) = set.symbol(&["item"]);
+  let (set_contains_fn_name, set_contains_export) = set.symbol(&["contains"]);
+  let (set_drop_fn_name, set_drop_export) = set.symbol(&["drop"]);
+
+  let cancellation_token = if cfg!(feature = "async") {
+    let token = Mangler::builtin("FinchCancellationToken", options)?;
+    let (token_new_fn_name, token_new_export) = token.symbol(&["new"]);
+    let (token_cancel_fn_name, token_cancel_export) = token.symbol(&["cancel"]);
+    let (token_drop_fn_name, token_drop_export) = token.symbol(&["drop"]);
+
+    quote!(
+      #token_new_export
+      pub unsafe extern fn #token_new_fn_name() -> *mut ::finch_gen::builtin::FinchCancellationToken {
+        Box::into_raw(Box::new(::finch_gen::builtin::FinchCancellationToken::new()))
+      }
+
+      #token_cancel_export
+      pub unsafe extern fn #token_cancel_fn_name(token: *const ::finch_gen::builtin::FinchCancellationToken) {
+        (*token).cancel()
+      }
+
+      #token_drop_export
+      pub unsafe extern fn #token_drop_fn_name(token: *mut ::finch_gen::builtin::FinchCancellationToken) {
+        drop(Box::from_raw(token))
+      }
+    )
+  } else {
+    proc_macro2::TokenStream::new()
+  };
+
+  Ok(quote!(
+    #[doc(hidden)]
+    #[allow(non_upper_case_globals)]
+    pub const ___finch_bindgen___export_builtins: () = ();
+
+    /// The `ABI_VERSION` of finch-gen this library was built with, checked by the bindings when loading it.
+    #abi_version_export
+    #[allow(non_upper_case_globals)]
+    pub static #abi_version_name: u32 = ::finch_gen::builtin::ABI_VERSION;
+
+    #cancellation_token
+
+    #string_new_export
     pub unsafe extern fn #string_new_fn_name(data: *const u8, len: usize) -> ::finch_gen::builtin::FinchString {
       ::finch_gen::builtin::FinchString::new(data, len)
     }
     
-    #[no_mangle]
+    #string_drop_export
     pub unsafe extern fn #string_drop_fn_name(value: ::finch_gen::builtin::FinchString) {
       drop(value);
     }
 
-    #[no_mangle]
+    #string16_new_export
+    pub unsafe extern fn #string16_new_fn_name(data: *const u16, len: usize) -> ::finch_gen::builtin::FinchString16 {
+      ::finch_gen::builtin::FinchString16::new(data, len)
+    }
+
+    #string16_drop_export
+    pub unsafe extern fn #string16_drop_fn_name(value: ::finch_gen::builtin::FinchString16) {
+      drop(value);
+    }
+
+    #bytes_new_export
+    pub unsafe extern fn #bytes_new_fn_name(len: usize) -> ::finch_gen::builtin::FinchBytes {
+      ::finch_gen::builtin::FinchBytes::new(len)
+    }
+
+    #bytes_drop_export
+    pub unsafe extern fn #bytes_drop_fn_name(value: ::finch_gen::builtin::FinchBytes) {
+      drop(value);
+    }
+
+    #cstring_new_export
+    pub unsafe extern fn #cstring_new_fn_name(ptr: *const ::std::os::raw::c_char) -> ::finch_gen::builtin::FinchResult<::finch_gen::builtin::FinchCString> {
+      match ::finch_gen::builtin::FinchCString::copy_from_ptr(ptr) {
+        Ok(x) => ::finch_gen::builtin::FinchResult::Ok(x),
+        Err(err) => ::finch_gen::builtin::FinchResult::Err(err),
+      }
+    }
+
+    #cstring_drop_export
     pub unsafe extern fn #cstring_drop_fn_name(value: ::finch_gen::builtin::FinchCString) {
       drop(value);
     }
-  )
+
+    #map_len_export
+    pub unsafe extern fn #map_len_fn_name(map: *const ::finch_gen::builtin::FinchMap<::std::os::raw::c_void, ::std::os::raw::c_void>) -> usize {
+      (*map).len
+    }
+
+    #map_key_export
+    pub unsafe extern fn #map_key_fn_name(map: *const ::finch_gen::builtin::FinchMap<::std::os::raw::c_void, ::std::os::raw::c_void>, index: usize) -> *const ::std::os::raw::c_void {
+      ((*(*map).vtable).key)(map, index)
+    }
+
+    #map_value_export
+    pub unsafe extern fn #map_value_fn_name(map: *const ::finch_gen::builtin::FinchMap<::std::os::raw::c_void, ::std::os::raw::c_void>, index: usize) -> *const ::std::os::raw::c_void {
+      ((*(*map).vtable).value)(map, index)
+    }
+
+    #map_get_export
+    pub unsafe extern fn #map_get_fn_name(map: *const ::finch_gen::builtin::FinchMap<::std::os::raw::c_void, ::std::os::raw::c_void>, key: *const ::std::os::raw::c_void) -> *const ::std::os::raw::c_void {
+      ((*(*map).vtable).get)(map, key)
+    }
+
+    #map_drop_export
+    pub unsafe extern fn #map_drop_fn_name(value: ::finch_gen::builtin::FinchMap<::std::os::raw::c_void, ::std::os::raw::c_void>) {
+      drop(value);
+    }
+
+    #set_len_export
+    pub unsafe extern fn #set_len_fn_name(set: *const ::finch_gen::builtin::FinchSet<::std::os::raw::c_void>) -> usize {
+      (*set).len
+    }
+
+    #set_item_export
+    pub unsafe extern fn #set_item_fn_name(set: *const ::finch_gen::builtin::FinchSet<::std::os::raw::c_void>, index: usize) -> *const ::std::os::raw::c_void {
+      ((*(*set).vtable).item)(set, index)
+    }
+
+    #set_contains_export
+    pub unsafe extern fn #set_contains_fn_name(set: *const ::finch_gen::builtin::FinchSet<::std::os::raw::c_void>, item: *const ::std::os::raw::c_void) -> bool {
+      ((*(*set).vtable).contains)(set, item)
+    }
+
+    #set_drop_export
+    pub unsafe extern fn #set_drop_fn_name(value: ::finch_gen::builtin::FinchSet<::std::os::raw::c_void>) {
+      drop(value);
+    }
+  ))
 }
\ No newline at end of file
@@ -0,0 +1,263 @@
+use syn::spanned::Spanned;
+use proc_macro::TokenStream;
+use syn::parse_quote;
+use quote::{quote, format_ident};
+
+use crate::{ToCType, abi, attr::Options, builtin, doc_filter, naming};
+use crate::diagnostic::{Diagnostic, DiagnosticLevel};
+
+/// Returns the trait implemented by a `dyn Trait` or `impl Trait` type,
+/// ignoring marker traits and lifetimes.
+pub fn trait_bound(bounds: &syn::punctuated::Punctuated<syn::TypeParamBound, syn::token::Add>) -> Option<&syn::Path> {
+  bounds.iter().filter_map(|bound| {
+    if let syn::TypeParamBound::Trait(bound) = bound {
+      match bound.path.segments.last().unwrap().ident.to_string().as_str() {
+        "Send" | "Sync" | "Unpin" | "Sized" => None,
+        _ => Some(&bound.path),
+      }
+    } else {
+      None
+    }
+  }).next()
+}
+
+fn is_closure(trait_path: &syn::Path) -> bool {
+  matches!(trait_path.segments.last().unwrap().ident.to_string().as_str(), "Fn" | "FnMut" | "FnOnce")
+}
+
+/// Returns the argument types and return type of an `Fn(A, B) -> C` bound.
+fn closure_sig(trait_path: &syn::Path) -> (Vec<syn::Type>, Option<syn::Type>) {
+  if let syn::PathArguments::Parenthesized(args) = &trait_path.segments.last().unwrap().arguments {
+    let output = if let syn::ReturnType::Type(_, ty) = &args.output {
+      Some(*ty.clone())
+    } else {
+      None
+    };
+
+    (args.inputs.iter().cloned().collect(), output)
+  } else {
+    (Vec::new(), None)
+  }
+}
+
+/// The C type a host passes in place of a callback trait or closure.
+pub fn callback_c_type(trait_path: &syn::Path) -> syn::Type {
+  if is_closure(trait_path) {
+    let (inputs, output) = closure_sig(trait_path);
+    let c_inputs = inputs.iter().map(|ty| ty.to_c_type());
+    let c_output = output.map(|ty| {
+      let ty = ty.to_c_type();
+      quote!(-> #ty)
+    });
+
+    parse_quote!(::finch_gen::builtin::FinchClosure<unsafe extern fn(*mut ::std::os::raw::c_void, #(#c_inputs),*) #c_output>)
+  } else {
+    parse_quote!(<dyn #trait_path as ::finch_gen::builtin::FinchCallback>::VTable)
+  }
+}
+
+/// Converts the value returned by a host callback. Callbacks have no error channel, so
+/// values which fail to convert, such as invalid strings, panic.
+fn convert_output(ty: &syn::Type, call: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+  let call = ty.convert_arg(call);
+  if crate::may_fail(ty, true) {
+    quote!(match (|| ::finch_gen::builtin::FinchResult::Ok(#call))() {
+      ::finch_gen::builtin::FinchResult::Ok(x) => x,
+      ::finch_gen::builtin::FinchResult::Err(err) => panic!("finch-gen: invalid value returned by callback: {}", err),
+      ::finch_gen::builtin::FinchResult::Cancelled => unreachable!(),
+    })
+  } else {
+    call
+  }
+}
+
+/// Converts the C value passed by the host into a value implementing the trait.
+pub fn callback_arg(trait_path: &syn::Path, body: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+  if is_closure(trait_path) {
+    let (inputs, output) = closure_sig(trait_path);
+    let arg_names: Vec<_> = (0..inputs.len()).map(|i| format_ident!("arg{}", i)).collect();
+    let converted_args = inputs.iter().zip(&arg_names).map(|(ty, name)| ty.convert_ret(quote!(#name)));
+    let call = quote!((closure.callback)(closure.user_data, #(#converted_args),*));
+
+    let (output, call) = if let Some(ty) = output {
+      let call = convert_output(&ty, call);
+      (quote!(-> #ty), call)
+    } else {
+      (proc_macro2::TokenStream::new(), call)
+    };
+
+    quote!({
+      let closure = #body;
+      move |#(#arg_names: #inputs),*| #output {
+        unsafe { #call }
+      }
+    })
+  } else {
+    body
+  }
+}
+
+pub fn make_callback(item: TokenStream, data: syn::ItemTrait, options: &Options) -> TokenStream {
+  let name = &data.ident;
+
+  match data.vis {
+    syn::Visibility::Public(_) => {}
+    _ => {
+      return Diagnostic::spanned(data.span(), DiagnosticLevel::Error, "finch-gen[E0008] trait not public but exported with #[finch_bindgen]")
+        .note("go to https://finch-gen.github.io/docs/errors/E0008 for more information")
+        .span_help(data.trait_token.span, "add 'pub' here")
+        .emit(item);
+    }
+  }
+
+  let mut fields = Vec::new();
+  let mut methods = Vec::new();
+
+  for trait_item in &data.items {
+    let method = match trait_item {
+      syn::TraitItem::Method(method) => method,
+      _ => {
+        return Diagnostic::spanned(trait_item.span(), DiagnosticLevel::Error, "finch-gen[E0009] callback traits may only contain methods")
+          .note("go to https://finch-gen.github.io/docs/errors/E0009 for more information")
+          .emit(item);
+      }
+    };
+
+    let sig = &method.sig;
+    let method_name = &sig.ident;
+
+    if !sig.generics.params.is_empty() || sig.asyncness.is_some() {
+      return Diagnostic::spanned(sig.span(), DiagnosticLevel::Error, "finch-gen[E0010] callback methods cannot be generic or async")
+        .note("go to https://finch-gen.github.io/docs/errors/E0010 for more information")
+        .emit(item);
+    }
+
+    match sig.inputs.first() {
+      Some(syn::FnArg::Receiver(receiver)) if receiver.reference.is_some() => {},
+      _ => {
+        return Diagnostic::spanned(sig.span(), DiagnosticLevel::Error, "finch-gen[E0011] callback methods must take '&self' or '&mut self'")
+          .note("go to https://finch-gen.github.io/docs/errors/E0011 for more information")
+          .emit(item);
+      }
+    }
+
+    let mut c_inputs = Vec::new();
+    let mut arg_names = Vec::new();
+    let mut converted_args = Vec::new();
+
+    for (i, input) in sig.inputs.iter().skip(1).enumerate() {
+      if let syn::FnArg::Typed(arg) = input {
+        let ty = &arg.ty;
+        let c_type = ty.to_c_type();
+        c_inputs.push(quote!(#c_type));
+
+        if let syn::Pat::Ident(pat) = &*arg.pat {
+          let arg_name = &pat.ident;
+          converted_args.push(ty.convert_ret(quote!(#arg_name)));
+          arg_names.push(quote!(#pat: #ty));
+        } else {
+          let arg_name = format_ident!("arg{}", i);
+          converted_args.push(ty.convert_ret(quote!(#arg_name)));
+          arg_names.push(quote!(#arg_name: #ty));
+        }
+      }
+    }
+
+    let (c_output, output, call) = if let syn::ReturnType::Type(_, ty) = &sig.output {
+      let c_type = ty.to_c_type();
+      let call = convert_output(ty, quote!((f)(self.user_data, #(#converted_args),*)));
+      (quote!(-> #c_type), quote!(-> #ty), call)
+    } else {
+      (proc_macro2::TokenStream::new(), proc_macro2::TokenStream::new(), quote!((f)(self.user_data, #(#converted_args),*)))
+    };
+
+    let receiver = sig.inputs.first().unwrap();
+    let doc_comments = method.attrs.iter().filter(doc_filter);
+
+    if let Some(default) = &method.default {
+      fields.push(quote!(
+        #(#doc_comments)
+        *
+        /// This method has a default implementation which is used when this is null.
+        pub #method_name: ::std::option::Option<unsafe extern fn(*mut ::std::os::raw::c_void, #(#c_inputs),*) #c_output>,
+      ));
+
+      methods.push(quote!(
+        fn #method_name(#receiver, #(#arg_names),*) #output {
+          if let ::std::option::Option::Some(f) = self.#method_name {
+            unsafe { #call }
+          } else #default
+        }
+      ));
+    } else {
+      fields.push(quote!(
+        #(#doc_comments)
+        *
+        pub #method_name: unsafe extern fn(*mut ::std::os::raw::c_void, #(#c_inputs),*) #c_output,
+      ));
+
+      methods.push(quote!(
+        fn #method_name(#receiver, #(#arg_names),*) #output {
+          let f = self.#method_name;
+          unsafe { #call }
+        }
+      ));
+    }
+  }
+
+  let doc_comments = data.attrs.iter().filter(doc_filter);
+  let mangler = match naming::Mangler::new("callback", name, options) {
+    Ok(mangler) => mangler,
+    Err(diagnostic) => return diagnostic.emit(item),
+  };
+  let vtable_name = mangler.ident(&["vtable"]);
+  let interface_hash = abi::make_interface_hash(&mangler, &fields);
+
+  let item = proc_macro2::TokenStream::from(item);
+
+  let builtins = builtin::assert_builtins();
+  let callback_impl = quote!(
+    #item
+
+    #(#doc_comments)
+    *
+    ///
+    /// Values passed to the callbacks, such as `FinchString` arguments, are owned by the
+    /// host, which must free them with the matching builtin, such as `___builtin___FinchString___drop`.
+    #[repr(C)]
+    #[allow(non_camel_case_types)]
+    pub struct #vtable_name {
+      /// Opaque pointer passed as the first argument to every callback.
+      pub user_data: *mut ::std::os::raw::c_void,
+      /// Called with `user_data` once Rust no longer needs this callback, may be null.
+      pub free: ::std::option::Option<unsafe extern fn(*mut ::std::os::raw::c_void)>,
+      #(#fields)*
+    }
+
+    impl #name for #vtable_name {
+      #(#methods)*
+    }
+
+    impl ::finch_gen::builtin::FinchCallback for dyn #name {
+      type VTable = #vtable_name;
+    }
+
+    impl Drop for #vtable_name {
+      fn drop(&mut self) {
+        if let ::std::option::Option::Some(free) = self.free {
+          unsafe { free(self.user_data) }
+        }
+      }
+    }
+
+    // Callbacks follow the same threading contract as `FinchClosure`, see its `Send` impl.
+    unsafe impl Send for #vtable_name {}
+    unsafe impl Sync for #vtable_name {}
+
+    #interface_hash
+
+    #builtins
+  );
+
+  TokenStream::from(callback_impl)
+}
@@ -0,0 +1,81 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_quote, spanned::Spanned};
+
+use crate::diagnostic::{Diagnostic, DiagnosticLevel};
+
+/// Implements `FinchConvert` for a struct with a single field, passed as that field,
+/// or for an enum without fields, passed as an `i32` discriminant.
+pub fn derive_convert(input: syn::DeriveInput) -> TokenStream {
+  let name = &input.ident;
+  let mut generics = input.generics.clone();
+
+  let convert_impl = match &input.data {
+    syn::Data::Struct(data) if data.fields.len() == 1 => {
+      let field = data.fields.iter().next().unwrap();
+      let ty = &field.ty;
+      let (member, construct) = match &field.ident {
+        Some(ident) => (quote!(#ident), quote!(Self { #ident: inner })),
+        None => (quote!(0), quote!(Self(inner))),
+      };
+
+      // The field may depend on type parameters, such as `struct Id<T>(T)`.
+      if generics.type_params().next().is_some() {
+        generics.make_where_clause().predicates.push(parse_quote!(#ty: ::finch_gen::builtin::FinchConvert));
+      }
+      let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+      quote!(
+        impl #impl_generics ::finch_gen::builtin::FinchConvert for #name #ty_generics #where_clause {
+          type Ffi = <#ty as ::finch_gen::builtin::FinchConvert>::Ffi;
+          const FALLIBLE: bool = <#ty as ::finch_gen::builtin::FinchConvert>::FALLIBLE;
+
+          fn to_ffi(self) -> Self::Ffi {
+            ::finch_gen::builtin::FinchConvert::to_ffi(self.#member)
+          }
+
+          fn from_ffi(ffi: Self::Ffi) -> ::std::result::Result<Self, ::finch_gen::builtin::FinchString> {
+            let inner = <#ty as ::finch_gen::builtin::FinchConvert>::from_ffi(ffi)?;
+            Ok(#construct)
+          }
+        }
+      )
+    },
+
+    syn::Data::Enum(data) if data.variants.iter().all(|variant| variant.fields.is_empty()) => {
+      let variants: Vec<_> = data.variants.iter().map(|variant| &variant.ident).collect();
+      let message = format!("invalid discriminant {{}} for '{}'", name);
+      let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+      quote!(
+        impl #impl_generics ::finch_gen::builtin::FinchConvert for #name #ty_generics #where_clause {
+          type Ffi = i32;
+          const FALLIBLE: bool = true;
+
+          fn to_ffi(self) -> i32 {
+            self as i32
+          }
+
+          fn from_ffi(ffi: i32) -> ::std::result::Result<Self, ::finch_gen::builtin::FinchString> {
+            #(
+              if ffi == Self::#variants as i32 {
+                return Ok(Self::#variants);
+              }
+            )*
+
+            Err(::finch_gen::builtin::FinchString::from(format!(#message, ffi)))
+          }
+        }
+      )
+    },
+
+    _ => {
+      return Diagnostic::spanned(input.span(), DiagnosticLevel::Error, "finch-gen[E0027] FinchConvert can only be derived for structs with a single field and enums without fields")
+        .note("go to https://finch-gen.github.io/docs/errors/E0027 for more information")
+        .help("implement finch_gen::FinchConvert by hand, or export the struct with #[finch_bindgen(value)]")
+        .emit(TokenStream::new());
+    },
+  };
+
+  TokenStream::from(convert_impl)
+}
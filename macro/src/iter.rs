@@ -0,0 +1,213 @@
+use quote::quote;
+use syn::{parse_quote, spanned::Spanned};
+
+use crate::{Borrowed, ToCType, borrowed, inject_panic_hook, naming::Mangler};
+use crate::diagnostic::{Diagnostic, DiagnosticLevel};
+
+/// Returns the `Item` of a trait object or `impl Trait` type implementing one of `traits`,
+/// looking through `Box` and `Pin`.
+fn trait_item(ty: &syn::Type, traits: &[&str]) -> Option<syn::Type> {
+  let bounds = match ty {
+    syn::Type::ImplTrait(ty) => &ty.bounds,
+    syn::Type::TraitObject(ty) => &ty.bounds,
+    syn::Type::Path(path) => {
+      let segment = path.path.segments.last().unwrap();
+      if segment.ident != "Box" && segment.ident != "Pin" {
+        return None;
+      }
+
+      if let syn::PathArguments::AngleBracketed(generics) = &segment.arguments {
+        if let Some(syn::GenericArgument::Type(ty)) = generics.args.first() {
+          return trait_item(ty, traits);
+        }
+      }
+
+      return None;
+    },
+    _ => return None,
+  };
+
+  for bound in bounds {
+    if let syn::TypeParamBound::Trait(bound) = bound {
+      let segment = bound.path.segments.last().unwrap();
+      if !traits.iter().any(|name| segment.ident == name) {
+        continue;
+      }
+
+      if let syn::PathArguments::AngleBracketed(generics) = &segment.arguments {
+        for arg in &generics.args {
+          if let syn::GenericArgument::Binding(binding) = arg {
+            if binding.ident == "Item" {
+              return Some(binding.ty.clone());
+            }
+          }
+        }
+      }
+    }
+  }
+
+  None
+}
+
+/// Returns the item type of an `impl Iterator<Item = T>` or `Box<dyn Iterator<Item = T>>`.
+pub fn iterator_item(ty: &syn::Type) -> Option<syn::Type> {
+  trait_item(ty, &["Iterator", "DoubleEndedIterator", "ExactSizeIterator"])
+}
+
+/// Returns the item type of an `impl Stream<Item = T>` or `Pin<Box<dyn Stream<Item = T>>>`.
+pub fn stream_item(ty: &syn::Type) -> Option<syn::Type> {
+  trait_item(ty, &["Stream"])
+}
+
+/// Returns the owned type of a borrowed item, such as `String` for `&str`, or `None` if
+/// the item is not borrowed. Items outlive the call to `___next` returning them, so
+/// they cannot be passed as views.
+fn owned_item(item_ty: &syn::Type) -> Result<Option<syn::Type>, Diagnostic> {
+  match borrowed(item_ty) {
+    Some(Borrowed::Str) => Ok(Some(parse_quote!(String))),
+    Some(Borrowed::Slice(elem, false)) => Ok(Some(parse_quote!(Vec<#elem>))),
+    Some(Borrowed::Os(ident)) if ident == "Path" => Ok(Some(parse_quote!(::std::path::PathBuf))),
+    Some(Borrowed::Os(_)) => Ok(Some(parse_quote!(::std::ffi::OsString))),
+    _ if matches!(item_ty, syn::Type::Reference(_)) => Err(
+      Diagnostic::spanned(item_ty.span(), DiagnosticLevel::Error, &format!("finch-gen[E0029] iterators and streams of '{}' are not supported", quote!(#item_ty)))
+        .note("go to https://finch-gen.github.io/docs/errors/E0029 for more information")
+        .help("return owned items instead, such as with `.cloned()`")
+    ),
+    _ => Ok(None),
+  }
+}
+
+/// Wraps the iterator returned by `body` in an opaque handle, returning the C return type,
+/// the converted body, and the `___next`, `___size_hint` and `___drop` functions for the handle.
+pub fn make_iterator(mangler: &Mangler, method_name: &str, item_ty: &syn::Type, body: proc_macro2::TokenStream) -> Result<(syn::Type, proc_macro2::TokenStream, Vec<proc_macro2::TokenStream>), Diagnostic> {
+  let (item_ty, body) = match owned_item(item_ty)? {
+    Some(owned) => (owned, quote!(::finch_gen::builtin::FinchIterator::new_owned(#body))),
+    None => (item_ty.clone(), quote!(::finch_gen::builtin::FinchIterator::new(#body))),
+  };
+  let iter_type: syn::Type = parse_quote!(::finch_gen::builtin::FinchIterator<#item_ty>);
+  let ret_type = parse_quote!(*mut #iter_type);
+
+  let item_c_type = item_ty.to_c_type();
+  let item_body = item_ty.convert_ret(quote!(x));
+
+  let (next_name, next_export) = mangler.symbol(&["iterator", method_name, "next"]);
+  let (size_hint_name, size_hint_export) = mangler.symbol(&["iterator", method_name, "size_hint"]);
+  let (drop_name, drop_export) = mangler.symbol(&["iterator", method_name, "drop"]);
+  let panic_hook = inject_panic_hook();
+
+  let functions = vec![
+    quote!(
+      /// Advances the iterator, returning `None` once it is exhausted.
+      #next_export
+      pub unsafe extern fn #next_name(iter: *mut #iter_type) -> ::finch_gen::builtin::FinchOption<#item_c_type> {
+        #panic_hook
+
+        if let Some(x) = ::std::iter::Iterator::next(&mut *iter) {
+          ::finch_gen::builtin::FinchOption::Some(#item_body)
+        } else {
+          ::finch_gen::builtin::FinchOption::None
+        }
+      }
+    ),
+
+    quote!(
+      /// Returns the lower and optional upper bound on the remaining length of the iterator.
+      #size_hint_export
+      pub unsafe extern fn #size_hint_name(iter: *const #iter_type) -> ::finch_gen::builtin::FinchTuple2<usize, ::finch_gen::builtin::FinchOption<usize>> {
+        let (lower, upper) = ::std::iter::Iterator::size_hint(&*iter);
+        ::finch_gen::builtin::FinchTuple2 {
+          _0: lower,
+          _1: if let Some(upper) = upper {
+            ::finch_gen::builtin::FinchOption::Some(upper)
+          } else {
+            ::finch_gen::builtin::FinchOption::None
+          },
+        }
+      }
+    ),
+
+    quote!(
+      #drop_export
+      pub unsafe extern fn #drop_name(iter: *mut #iter_type) {
+        drop(Box::from_raw(iter))
+      }
+    ),
+  ];
+
+  Ok((ret_type, body, functions))
+}
+
+/// Wraps the stream returned by `body` in an opaque handle, returning the C return type,
+/// the converted body, and the `___next`, `___next_callback`, `___cancel` and `___drop`
+/// functions for the handle.
+pub fn make_stream(mangler: &Mangler, method_name: &str, item_ty: &syn::Type, body: proc_macro2::TokenStream) -> Result<(syn::Type, proc_macro2::TokenStream, Vec<proc_macro2::TokenStream>), Diagnostic> {
+  let (item_ty, body) = match owned_item(item_ty)? {
+    Some(owned) => (owned, quote!(::finch_gen::builtin::FinchStream::new_owned(#body))),
+    None => (item_ty.clone(), quote!(::finch_gen::builtin::FinchStream::new(#body))),
+  };
+  let stream_type: syn::Type = parse_quote!(::finch_gen::builtin::FinchStream<#item_ty>);
+  let ret_type = parse_quote!(*mut #stream_type);
+
+  let item_c_type = item_ty.to_c_type();
+  let item_body = item_ty.convert_ret(quote!(x));
+
+  let (next_name, next_export) = mangler.symbol(&["stream", method_name, "next"]);
+  let (next_callback_name, next_callback_export) = mangler.symbol(&["stream", method_name, "next_callback"]);
+  let (cancel_name, cancel_export) = mangler.symbol(&["stream", method_name, "cancel"]);
+  let (drop_name, drop_export) = mangler.symbol(&["stream", method_name, "drop"]);
+  let panic_hook = inject_panic_hook();
+
+  let functions = vec![
+    quote!(
+      /// Blocks until the next item is ready, returning `None` once the stream
+      /// has finished or been cancelled. Returns `Err` if called from a runtime
+      /// thread, such as from inside a `___next_callback` callback.
+      #next_export
+      pub unsafe extern fn #next_name(stream: *mut #stream_type) -> ::finch_gen::builtin::FinchResult<::finch_gen::builtin::FinchOption<#item_c_type>> {
+        #panic_hook
+
+        match (*stream).next() {
+          Ok(Some(x)) => ::finch_gen::builtin::FinchResult::Ok(::finch_gen::builtin::FinchOption::Some(#item_body)),
+          Ok(None) => ::finch_gen::builtin::FinchResult::Ok(::finch_gen::builtin::FinchOption::None),
+          Err(err) => ::finch_gen::builtin::FinchResult::Err(::finch_gen::builtin::FinchString::from(err.to_string())),
+        }
+      }
+    ),
+
+    quote!(
+      /// Returns immediately, calling `callback` from a runtime thread once the next item is ready.
+      /// The stream must not be advanced again until the callback has been called.
+      #next_callback_export
+      pub unsafe extern fn #next_callback_name(stream: *mut #stream_type, callback: ::finch_gen::builtin::FinchClosure<unsafe extern fn(*mut ::std::os::raw::c_void, ::finch_gen::builtin::FinchOption<#item_c_type>)>) {
+        #panic_hook
+
+        (*stream).next_with(move |x| {
+          let x = if let Some(x) = x {
+            ::finch_gen::builtin::FinchOption::Some(#item_body)
+          } else {
+            ::finch_gen::builtin::FinchOption::None
+          };
+
+          unsafe { (callback.callback)(callback.user_data, x) }
+        });
+      }
+    ),
+
+    quote!(
+      /// Cancels the stream, any pending or future calls to `next` return `None`.
+      #cancel_export
+      pub unsafe extern fn #cancel_name(stream: *mut #stream_type) {
+        (*stream).cancel()
+      }
+    ),
+
+    quote!(
+      #drop_export
+      pub unsafe extern fn #drop_name(stream: *mut #stream_type) {
+        drop(Box::from_raw(stream))
+      }
+    ),
+  ];
+
+  Ok((ret_type, body, functions))
+}
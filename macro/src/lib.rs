@@ -1,35 +1,121 @@
 #![cfg_attr(nightly, feature(proc_macro_diagnostic))]
 
-use std::sync::Once;
-use std::sync::Mutex;
 use std::iter::FromIterator;
-use std::collections::HashSet;
 use syn::spanned::Spanned;
 use proc_macro::TokenStream;
-use lazy_static::lazy_static;
 use syn::{parse_macro_input, parse_quote};
-use quote::{quote, format_ident};
+use quote::{quote, quote_spanned, format_ident};
 
+mod abi;
+mod attr;
 mod builtin;
+mod callback;
+mod convert;
 mod diagnostic;
+mod iter;
+mod naming;
+mod native;
+mod strings;
+mod thread;
+mod traits;
+mod value;
 use diagnostic::{Diagnostic, DiagnosticLevel};
 
-static INJECT: Once = Once::new();
-
-lazy_static! {
-  static ref CLASS_ERROR: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
-}
+/// The largest tuple with a matching `FinchTuple` builtin.
+const MAX_TUPLE_LEN: usize = 12;
 
 fn doc_filter<'r>(x: &'r &syn::Attribute) -> bool {
   x.path.segments.first().unwrap().ident.to_string() == "doc"
 }
 
-fn crate_name() -> String {
-  std::env::var("CARGO_PKG_NAME").unwrap().replace("-", "_")
+/// The crate name used in exported symbols.
+///
+/// This is read from `FINCH_GEN_CRATE_NAME` if set, for example by a build script
+/// with `cargo:rustc-env=FINCH_GEN_CRATE_NAME=...`, then from the name Cargo compiles
+/// the library as, which follows `[lib] name` rather than the package name.
+fn crate_name() -> Result<String, Diagnostic> {
+  for var in &["FINCH_GEN_CRATE_NAME", "CARGO_CRATE_NAME", "CARGO_PKG_NAME"] {
+    if let Ok(name) = std::env::var(var) {
+      let name = name.replace("-", "_");
+      if name.is_empty() {
+        continue;
+      }
+
+      if name.starts_with(|c: char| c.is_ascii_digit()) || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(
+          Diagnostic::spanned(proc_macro2::Span::call_site(), DiagnosticLevel::Error, &format!("finch-gen[E0021] invalid crate name '{}' in {}", name, var))
+            .note("go to https://finch-gen.github.io/docs/errors/E0021 for more information")
+        );
+      }
+
+      return Ok(name);
+    }
+  }
+
+  Err(
+    Diagnostic::spanned(proc_macro2::Span::call_site(), DiagnosticLevel::Error, "finch-gen[E0021] could not determine the crate name")
+      .note("go to https://finch-gen.github.io/docs/errors/E0021 for more information")
+      .help("set the FINCH_GEN_CRATE_NAME environment variable, or pass #[finch_bindgen(prefix = \"...\")]")
+  )
+}
+
+/// Exports the builtin functions used by every generated binding, such as
+/// `FinchString___new` and `FinchString___drop`.
+///
+/// This must be invoked exactly once, at the root of any crate using `#[finch_bindgen]`.
+///
+/// Takes the same `prefix` and `naming` options as `#[finch_bindgen]`, such as
+/// `export_builtins!(prefix = "mylib", naming = "snake")`, to name the builtins.
+#[proc_macro]
+pub fn export_builtins(input: TokenStream) -> TokenStream {
+  let args = parse_macro_input!(input as syn::AttributeArgs);
+  let unsupported = args.iter().find(|arg| {
+    !matches!(arg, syn::NestedMeta::Meta(syn::Meta::NameValue(value)) if value.path.is_ident("prefix") || value.path.is_ident("naming"))
+  });
+
+  if let Some(arg) = unsupported {
+    return Diagnostic::spanned(arg.span(), DiagnosticLevel::Error, &format!("finch-gen[E0017] unsupported option '{}' for export_builtins!()", quote!(#arg)))
+      .note("go to https://finch-gen.github.io/docs/errors/E0017 for more information")
+      .help("export_builtins!() only takes the `prefix` and `naming` options")
+      .emit(TokenStream::new());
+  }
+
+  let options = match attr::Options::parse(&args) {
+    Ok(options) => options,
+    Err(diagnostic) => return diagnostic.emit(TokenStream::new()),
+  };
+
+  // Proc macros cannot tell Cargo which environment variables they read, but `option_env!`
+  // can, so the crate is rebuilt when any of them change.
+  let track_env = quote!(
+    const _: [Option<&str>; 3] = [
+      option_env!("FINCH_GEN_CRATE_NAME"),
+      option_env!("FINCH_GEN_OWNERSHIP"),
+      option_env!("FINCH_GEN_STRING"),
+    ];
+  );
+
+  match builtin::make_builtin(&options) {
+    Ok(builtins) => TokenStream::from(quote!(#builtins #track_env)),
+    Err(diagnostic) => diagnostic.emit(TokenStream::from(track_env)),
+  }
+}
+
+/// Implements `FinchConvert` for a struct with a single field, such as `struct UserId(u64)`,
+/// or for an enum without fields.
+#[proc_macro_derive(FinchConvert)]
+pub fn derive_finch_convert(input: TokenStream) -> TokenStream {
+  convert::derive_convert(parse_macro_input!(input as syn::DeriveInput))
 }
 
 #[proc_macro_attribute]
-pub fn finch_bindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn finch_bindgen(attr: TokenStream, item: TokenStream) -> TokenStream {
+  let args = parse_macro_input!(attr as syn::AttributeArgs);
+  let options = match attr::Options::parse(&args) {
+    Ok(options) => options,
+    Err(diagnostic) => return diagnostic.emit(item),
+  };
+
   let cloned = item.clone();
   let input = parse_macro_input!(cloned as syn::Item);
 
@@ -37,18 +123,28 @@ pub fn finch_bindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
     syn::Item::Struct(data) => {
       let name = &data.ident;
 
-      match data.vis {
-        syn::Visibility::Public(_) => {}
-        _ => {
-          CLASS_ERROR.lock().unwrap().insert(name.to_string());
-
-          return Diagnostic::spanned(data.span(), DiagnosticLevel::Error, "finch-gen[E0001] struct not public but exported with #[finch_bindgen]")
+      // The struct is still expanded, so its impls do not also fail as if it had not been exported.
+      let vis_diagnostic = match data.vis {
+        syn::Visibility::Public(_) => None,
+        _ => Some(
+          Diagnostic::spanned(data.span(), DiagnosticLevel::Error, "finch-gen[E0001] struct not public but exported with #[finch_bindgen]")
             .note("go to https://finch-gen.github.io/docs/errors/E0001 for more information")
             .span_help(data.struct_token.span, "add 'pub' here")
-            .emit(item);
-        }
+        ),
+      };
+
+      if options.value {
+        let value_impl = value::make_value(item, data, &options);
+        return match vis_diagnostic {
+          Some(diagnostic) => diagnostic.emit(value_impl),
+          None => value_impl,
+        };
       }
 
+      let mangler = match naming::Mangler::new("class", name, &options) {
+        Ok(mangler) => mangler,
+        Err(diagnostic) => return diagnostic.emit(item),
+      };
       let mut functions = Vec::new();
 
       match &data.fields {
@@ -66,31 +162,43 @@ pub fn finch_bindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
               //   }
               // }
 
+              let field_options = match attr::Options::from_attrs(&field.attrs) {
+                Ok(options) => options,
+                Err(diagnostic) => return diagnostic.emit(TokenStream::new()),
+              };
+
               let field_name = field.clone().ident.unwrap();
               let field_type = &field.ty;
+              let member_name = naming::member_name(&field_name, &field_options);
+              let field_mangler = mangler.with_options(&field_options);
               let doc_comments = field.attrs.iter().filter(doc_filter);
+              let panic_hook = inject_panic_hook();
 
               if readable {
-                let getter_name = format_ident!("___finch_bindgen___{}___class___{}___getter___{}", crate_name(), name, field_name);
+                let (getter_name, getter_export) = field_mangler.symbol(&["getter", &member_name]);
                 let doc_comments_getter = doc_comments.clone();
                 functions.push(quote!(
                   #(#doc_comments_getter)
                   *
-                  #[no_mangle]
-                  pub unsafe extern fn #getter_name(&self) -> #field_type {
-                    self.#field_name
+                  #getter_export
+                  pub unsafe extern fn #getter_name(this: ::finch_gen::builtin::class::Handle<Self>) -> #field_type {
+                    #panic_hook
+
+                    ::finch_gen::builtin::class::get::<Self>(this).#field_name
                   }
                 ));
               }
 
               if writeable {
-                let setter_name = format_ident!("___finch_bindgen___{}___class___{}___setter___{}", crate_name(), name, field_name);
+                let (setter_name, setter_export) = field_mangler.symbol(&["setter", &member_name]);
                 functions.push(quote!(
                   #(#doc_comments)
                   *
-                  #[no_mangle]
-                  pub unsafe extern fn #setter_name(&mut self, value: #field_type) {
-                    self.#field_name = value
+                  #setter_export
+                  pub unsafe extern fn #setter_name(this: ::finch_gen::builtin::class::Handle<Self>, value: #field_type) {
+                    #panic_hook
+
+                    ::finch_gen::builtin::class::get_mut::<Self>(this).#field_name = value
                   }
                 ));
               }
@@ -101,42 +209,115 @@ pub fn finch_bindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
         _ => {}
       }
 
+      let struct_traits = traits::Traits::from_attrs(&data.attrs, &options);
+      functions.extend(traits::make_trait_functions(&mangler, "class", &struct_traits));
+
       let doc_comments = data.attrs.iter().filter(doc_filter);
 
-      let drop_name = format_ident!("___finch_bindgen___{}___class___{}___drop", crate_name(), name);
-      let new_name = format_ident!("___finch_bindgen___{}___class___{}___type", crate_name(), name);
+      let (drop_name, drop_export) = mangler.symbol(&["drop"]);
+      let panic_hook = inject_panic_hook();
+      functions.insert(0, quote!(
+        #drop_export
+        pub unsafe extern fn #drop_name(this: ::finch_gen::builtin::class::Handle<Self>) {
+          #panic_hook
+
+          ::finch_gen::builtin::class::drop_handle::<Self>(this)
+        }
+      ));
+
+      let mut data = data.clone();
+      for field in data.fields.iter_mut() {
+        attr::strip_attrs(&mut field.attrs);
+      }
+
+      // An invalid default is reported once, and the class still expanded, so its impls
+      // do not also fail as if it had not been exported.
+      let (ownership, env_diagnostic) = match options.ownership.map(Ok).unwrap_or_else(attr::Ownership::crate_default) {
+        Ok(ownership) => (ownership, None),
+        Err(diagnostic) => (attr::Ownership::Boxed, Some(diagnostic)),
+      };
+
+      if let attr::Ownership::Shared | attr::Ownership::SharedMutex = ownership {
+        let (retain_name, retain_export) = mangler.symbol(&["retain"]);
+        let (release_name, release_export) = mangler.symbol(&["release"]);
+        functions.push(quote!(
+          /// Returns a new handle to the same object, which must be released separately.
+          #retain_export
+          pub unsafe extern fn #retain_name(this: ::finch_gen::builtin::class::Handle<Self>) -> ::finch_gen::builtin::class::Handle<Self> {
+            ::finch_gen::builtin::class::retain::<Self>(this)
+          }
+        ));
+        functions.push(quote!(
+          /// Releases this handle, dropping the object once every handle to it is released.
+          #release_export
+          pub unsafe extern fn #release_name(this: ::finch_gen::builtin::class::Handle<Self>) {
+            #panic_hook
 
-      let item = proc_macro2::TokenStream::from(item);
+            ::finch_gen::builtin::class::drop_handle::<Self>(this)
+          }
+        ));
+      }
 
-      let boilerplate = inject_boilerplate();
+      let thread_safety = thread::make_thread_safety(&mangler, &data.ident, ownership, &options);
+
+      let ownership = match ownership {
+        attr::Ownership::Boxed => quote!(::finch_gen::builtin::class::Boxed),
+        attr::Ownership::Checked => quote!(::finch_gen::builtin::class::Checked),
+        attr::Ownership::Handle => quote!(::finch_gen::builtin::class::Handles),
+        attr::Ownership::Shared => quote!(::finch_gen::builtin::class::Shared),
+        attr::Ownership::SharedMutex => quote!(::finch_gen::builtin::class::SharedMutex),
+        attr::Ownership::Locked => quote!(::finch_gen::builtin::class::Locked),
+        attr::Ownership::ThreadBound => quote!(::finch_gen::builtin::class::ThreadBound),
+      };
+
+      // The handles passed to the host depend on the ownership backend.
+      let mut items = vec![ownership.clone()];
+      items.extend(functions.iter().cloned());
+
+      let new_name = mangler.ident(&["type"]);
+      let naming_key = mangler.key();
+      let interface_hash = abi::make_interface_hash(&mangler, &items);
+
+      if let Err(diagnostic) = mangler.check_collisions(data.ident.span()) {
+        return diagnostic.emit(TokenStream::from(quote!(#data)));
+      }
+      let symbols = mangler.exported();
+
+      let name = &data.ident;
+      let builtins = builtin::assert_builtins();
       let class_impl = quote!(
-        #item
+        #data
 
         #(#doc_comments)
         *
         #[allow(non_camel_case_types)]
         type #new_name = #name;
 
+        unsafe impl ::finch_gen::builtin::FinchClass for #name {
+          const NAMING: &'static str = #naming_key;
+          const SYMBOLS: &'static [&'static str] = &[#(#symbols),*];
+          type Ownership = #ownership;
+        }
+
         #[allow(non_snake_case)]
         impl #new_name {
-          #[no_mangle]
-          pub unsafe extern fn #drop_name(ptr: *mut Self) {
-            drop(Box::from_raw(ptr))
-          }
-
           #(#functions)*
         }
 
-        #boilerplate
+        #thread_safety
+
+        #interface_hash
+
+        #builtins
       );
 
-      TokenStream::from(class_impl)
+      env_diagnostic.into_iter().chain(vis_diagnostic).fold(TokenStream::from(class_impl), |tokens, diagnostic| diagnostic.emit(tokens))
     }
 
-    syn::Item::Impl(input) => {
+    syn::Item::Impl(mut input) => {
       let name;
       if let syn::Type::Path(path) = *input.self_ty.clone() {
-        name = path.path.segments.first().unwrap().ident.clone();
+        name = path.path.segments.last().unwrap().ident.clone();
       } else {
         let ty = input.self_ty;
         return Diagnostic::spanned(ty.span(), DiagnosticLevel::Error, &format!("finch-gen[E0005] invalid type found: expected path, got '{}'", quote!(#ty)))
@@ -144,16 +325,85 @@ pub fn finch_bindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
           .emit(TokenStream::new());
       }
 
-      if CLASS_ERROR.lock().unwrap().contains(&name.to_string()) {
-        return item;
-      }
+      let kind = if options.value { "value" } else { "class" };
+      let mangler = match naming::Mangler::new(kind, &name, &options) {
+        Ok(mangler) => mangler,
+        Err(diagnostic) => return diagnostic.emit(item),
+      };
 
       let mut functions = Vec::new();
+      let mut statics = Vec::new();
+      let mut env_diagnostic = None;
 
       for item in &input.items {
         match item {
           syn::ImplItem::Method(method) => {
             if let syn::Visibility::Public(_) = method.vis {
+              let method_options = match attr::Options::from_attrs(&method.attrs) {
+                Ok(options) => options,
+                Err(diagnostic) => return diagnostic.emit(TokenStream::new()),
+              };
+
+              let mut method = method.clone();
+              if options.value {
+                for input in method.sig.inputs.iter_mut() {
+                  if let syn::FnArg::Typed(arg) = input {
+                    *arg.ty = value::replace_self(&arg.ty, &name);
+                  }
+                }
+
+                if let syn::ReturnType::Type(_, ty) = &mut method.sig.output {
+                  **ty = value::replace_self(ty, &name);
+                }
+              }
+
+              let strings = match method_options.strings.or(options.strings).map(Ok).unwrap_or_else(attr::Strings::crate_default) {
+                Ok(strings) => strings,
+                Err(diagnostic) => {
+                  env_diagnostic.get_or_insert(diagnostic);
+                  attr::Strings::Finch
+                },
+              };
+
+              if strings != attr::Strings::Finch {
+                for input in method.sig.inputs.iter_mut() {
+                  if let syn::FnArg::Typed(arg) = input {
+                    *arg.ty = strings::apply(&arg.ty, strings, true);
+                  }
+                }
+
+                if let syn::ReturnType::Type(_, ty) = &mut method.sig.output {
+                  **ty = strings::apply(ty, strings, false);
+                }
+              }
+
+              for input in &method.sig.inputs {
+                if let syn::FnArg::Typed(arg) = input {
+                  if let Err(diagnostic) = check_borrowed(&arg.ty) {
+                    return diagnostic.emit(TokenStream::new());
+                  }
+                }
+              }
+              if let syn::ReturnType::Type(_, ty) = &method.sig.output {
+                if let Err(diagnostic) = check_borrowed(ty) {
+                  return diagnostic.emit(TokenStream::new());
+                }
+              }
+
+              // Fallible conversions return early with an error, so the function must return a `FinchResult`.
+              // Whether a `FinchConvert` type can fail is only known to the compiler, which selects the
+              // return type below when no other conversion is fallible.
+              let definitely_fallible = method.sig.inputs.iter().any(|input| matches!(input, syn::FnArg::Typed(arg) if is_fallible(&arg.ty, true)))
+                || matches!(&method.sig.output, syn::ReturnType::Type(_, ty) if is_fallible(ty, false));
+              let mut converted_types = Vec::new();
+              for input in &method.sig.inputs {
+                if let syn::FnArg::Typed(arg) = input {
+                  convert_types(&arg.ty, &mut converted_types);
+                }
+              }
+              let fallible = definitely_fallible || !converted_types.is_empty();
+              let mut fallible_ret = None;
+
               let method_name = &method.sig.ident;
               let mut inputs = Vec::from_iter(method.sig.inputs.clone());
               let mut input_names = Vec::new();
@@ -168,21 +418,28 @@ pub fn finch_bindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
                 }
               }
 
-              let int_method_name;
+              let method_kind;
               let fn_body;
               let mut extra_comments = quote!();
 
               if method.sig.inputs.len() > 0 {
                 match method.sig.inputs.first().unwrap() {
                   syn::FnArg::Receiver(receiver) => {
-                    if receiver.reference.is_some() {
-                      int_method_name = format_ident!("___finch_bindgen___{}___class___{}___method___{}", crate_name(), name, method_name);
+                    if options.value {
+                      method_kind = "method";
                       fn_body = quote!(self.#method_name(#(#input_names),*));
+                    } else if receiver.reference.is_some() {
+                      method_kind = "method";
+                      inputs[0] = parse_quote!(this: ::finch_gen::builtin::class::Handle<Self>);
+                      fn_body = if receiver.mutability.is_some() {
+                        quote!(::finch_gen::builtin::class::get_mut::<Self>(this).#method_name(#(#input_names),*))
+                      } else {
+                        quote!(::finch_gen::builtin::class::get::<Self>(this).#method_name(#(#input_names),*))
+                      };
                     } else {
-                      int_method_name = format_ident!("___finch_bindgen___{}___class___{}___method_consume___{}", crate_name(), name, method_name);
-                      inputs.remove(0);
-                      inputs.insert(0, parse_quote!(ptr: *mut Self));
-                      fn_body = quote!(Box::from_raw(ptr).#method_name(#(#input_names),*));
+                      method_kind = "method_consume";
+                      inputs[0] = parse_quote!(this: ::finch_gen::builtin::class::Handle<Self>);
+                      fn_body = quote!(::finch_gen::builtin::class::take::<Self>(this).#method_name(#(#input_names),*));
                       extra_comments = quote!(
                         /// This method consumes the internal pointer.
                         /// You cannot call any methods, or get/set any values
@@ -191,32 +448,62 @@ pub fn finch_bindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
                     }
                   },
 
+                  syn::FnArg::Typed(arg) if matches!(&*arg.pat, syn::Pat::Ident(pat) if pat.ident == "self") => {
+                    if options.value || !is_arc_self(&arg.ty) {
+                      let ty = &arg.ty;
+                      return Diagnostic::spanned(arg.span(), DiagnosticLevel::Error, &format!("finch-gen[E0032] unsupported receiver type '{}'", quote!(#ty)))
+                        .note("only 'self', '&self', '&mut self' and, for classes exported with #[finch_bindgen(shared)], 'self: Arc<Self>' are supported")
+                        .note("go to https://finch-gen.github.io/docs/errors/E0032 for more information")
+                        .emit(TokenStream::new());
+                    }
+
+                    // The host keeps its handle, so the receiver is a new reference to the object.
+                    method_kind = "method";
+                    inputs[0] = parse_quote!(this: ::finch_gen::builtin::class::Handle<Self>);
+                    let args = &input_names[1..];
+                    fn_body = quote!(::finch_gen::builtin::class::to_arc::<Self>(this).#method_name(#(#args),*));
+                  },
+
                   syn::FnArg::Typed(_) => {
-                    int_method_name = format_ident!("___finch_bindgen___{}___class___{}___static___{}", crate_name(), name, method_name);
+                    method_kind = "static";
                     fn_body = quote!(Self::#method_name(#(#input_names),*));
                   }
                 }
               } else {
-                int_method_name = format_ident!("___finch_bindgen___{}___class___{}___static___{}", crate_name(), name, method_name);
+                method_kind = "static";
                 fn_body = quote!(Self::#method_name(#(#input_names),*));
               }
 
+              let member_name = naming::member_name(method_name, &method_options);
+              let method_mangler = mangler.with_options(&method_options);
+              let (int_method_name, method_export) = method_mangler.symbol(&[method_kind, &member_name]);
+
+              for (i, input) in method.sig.inputs.iter().enumerate() {
+                if let syn::FnArg::Typed(arg) = input {
+                  let position = match &*arg.pat {
+                    syn::Pat::Ident(pat) => pat.ident.to_string(),
+                    _ => i.to_string(),
+                  };
+                  statics.push(native::make_metadata(&method_mangler, &[method_kind, &member_name], &position, &arg.ty));
+                }
+              }
+              if let syn::ReturnType::Type(_, ty) = &method.sig.output {
+                statics.push(native::make_metadata(&method_mangler, &[method_kind, &member_name], "return", ty));
+              }
+
+              let call_body = fn_body.clone();
               let fn_body = if let Some(asyncness) = method.sig.asyncness {
-                if cfg!(feature = "async") {
-                  quote!({
-                    let mut rt = finch_gen::builtin::RUNTIME.get_mut();
-                    if rt.is_none() {
-                      *rt = ::std::option::Option::Some(::tokio::runtime::Runtime::new().expect("failed to create tokio runtime"));
-                    }
-  
-                    if let ::std::option::Option::Some(rt) = rt {
-                      rt.block_on(async {
-                        #fn_body.await
-                      })
-                    } else {
-                      panic!("failed to get tokio runtime")
-                    }
+                if cfg!(feature = "async") && fallible {
+                  // Arguments are converted inside the future, so their errors are returned from it.
+                  quote!(match ::finch_gen::builtin::block_on(async { ::finch_gen::builtin::FinchResult::Ok(#fn_body.await) }) {
+                    ::finch_gen::builtin::FinchResult::Ok(x) => x,
+                    ::finch_gen::builtin::FinchResult::Err(err) => return ::finch_gen::builtin::FinchResult::Err(err),
+                    ::finch_gen::builtin::FinchResult::Cancelled => return ::finch_gen::builtin::FinchResult::Cancelled,
                   })
+                } else if cfg!(feature = "async") {
+                  quote!(::finch_gen::builtin::block_on(async {
+                    #fn_body.await
+                  }))
                 } else {
                   return Diagnostic::spanned(asyncness.span, DiagnosticLevel::Error, "finch-gen[E0002] found async function but the 'async' feature is not enabled")
                     .note("go to https://finch-gen.github.io/docs/errors/E0002 for more information")
@@ -227,17 +514,107 @@ pub fn finch_bindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
                 fn_body
               };
 
+              let mut cancellable = method.sig.asyncness.is_some();
               let ret_expr;
               let body;
               if let syn::ReturnType::Type(_, ty) = &method.sig.output {
-                let ret_type = ty.to_c_type();
-                ret_expr = quote!(-> #ret_type);
-                body = ty.convert_ret(fn_body);
+                // Views, iterators and streams may borrow from the receiver or any class argument,
+                // which must not be locked as the lock is released when the function returns.
+                let mut owners = Vec::new();
+                if method_kind == "method" && !options.value {
+                  owners.push(quote_spanned!(ty.span() => Self));
+                }
+                for input in &method.sig.inputs {
+                  if let syn::FnArg::Typed(arg) = input {
+                    if let Some(Borrowed::Class(elem, _)) = borrowed(&arg.ty) {
+                      owners.push(quote!(#elem));
+                    }
+                  }
+                }
+                let view_assertions = quote!(#(::finch_gen::builtin::class::assert_view::<#owners>();)*);
+
+                if let Some(item_ty) = method_options.iterator.clone().or_else(|| iter::iterator_item(ty)) {
+                  cancellable = false;
+                  let (ret_type, iter_body, iter_functions) = match iter::make_iterator(&method_mangler, &member_name, &item_ty, quote!({ #view_assertions #fn_body })) {
+                    Ok(iterator) => iterator,
+                    Err(diagnostic) => return diagnostic.emit(TokenStream::new()),
+                  };
+                  if fallible {
+                    ret_expr = quote!(-> ::finch_gen::builtin::FinchResult<#ret_type>);
+                    body = quote!(::finch_gen::builtin::FinchResult::Ok(#iter_body));
+                    fallible_ret = Some(quote!(#ret_type));
+                  } else {
+                    ret_expr = quote!(-> #ret_type);
+                    body = iter_body;
+                  }
+                  functions.extend(iter_functions);
+                  if is_borrowed(ty) {
+                    statics.push(borrowed_marker(&method_mangler, method_kind, &member_name));
+                  }
+                } else if let Some(item_ty) = method_options.stream.clone().or_else(|| iter::stream_item(ty)) {
+                  cancellable = false;
+                  if !cfg!(feature = "async") {
+                    return Diagnostic::spanned(ty.span(), DiagnosticLevel::Error, "finch-gen[E0016] found stream but the 'async' feature is not enabled")
+                      .note("go to https://finch-gen.github.io/docs/errors/E0016 for more information")
+                      .help("enable the 'async' feature for finch-gen in your Cargo.toml")
+                      .emit(TokenStream::new());
+                  }
+
+                  let (ret_type, stream_body, stream_functions) = match iter::make_stream(&method_mangler, &member_name, &item_ty, quote!({ #view_assertions #fn_body })) {
+                    Ok(stream) => stream,
+                    Err(diagnostic) => return diagnostic.emit(TokenStream::new()),
+                  };
+                  if fallible {
+                    ret_expr = quote!(-> ::finch_gen::builtin::FinchResult<#ret_type>);
+                    body = quote!(::finch_gen::builtin::FinchResult::Ok(#stream_body));
+                    fallible_ret = Some(quote!(#ret_type));
+                  } else {
+                    ret_expr = quote!(-> #ret_type);
+                    body = stream_body;
+                  }
+                  functions.extend(stream_functions);
+                  if is_borrowed(ty) {
+                    statics.push(borrowed_marker(&method_mangler, method_kind, &member_name));
+                  }
+                } else if fallible && !ty.is_result() {
+                  let ret_type = ty.to_c_type();
+                  let ok_body = ty.convert_ret(fn_body);
+                  ret_expr = quote!(-> ::finch_gen::builtin::FinchResult<#ret_type>);
+                  body = quote!(::finch_gen::builtin::FinchResult::Ok(#ok_body));
+                  fallible_ret = Some(quote!(#ret_type));
+                } else {
+                  let ret_type = ty.to_c_type();
+                  ret_expr = quote!(-> #ret_type);
+
+                  if is_borrowed(ty) {
+                    let converted = ty.convert_ret(fn_body);
+                    body = quote!({
+                      #view_assertions
+                      #converted
+                    });
+
+                    statics.push(borrowed_marker(&method_mangler, method_kind, &member_name));
+                  } else {
+                    body = ty.convert_ret(fn_body);
+                  }
+                }
+              } else if fallible {
+                ret_expr = quote!(-> ::finch_gen::builtin::FinchResult<()>);
+                body = quote!(::finch_gen::builtin::FinchResult::Ok(#fn_body));
+                fallible_ret = Some(quote!(()));
               } else {
                 ret_expr = proc_macro2::TokenStream::new();
                 body = fn_body;
               }
 
+              let (ret_expr, body) = match fallible_ret {
+                Some(ret_type) if !definitely_fallible => {
+                  let outcome = quote!(<::finch_gen::builtin::Fallible<{ false #(|| <#converted_types as ::finch_gen::builtin::FinchConvert>::FALLIBLE)* }> as ::finch_gen::builtin::Outcome<#ret_type>>);
+                  (quote!(-> #outcome::Output), quote!(#outcome::wrap((|| #body)())))
+                },
+                _ => (ret_expr, body),
+              };
+
               let inputs: syn::punctuated::Punctuated<syn::FnArg, syn::token::Comma> = syn::punctuated::Punctuated::from_iter(
                 inputs.into_iter().map(|x| {
                   match &x {
@@ -259,13 +636,57 @@ pub fn finch_bindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
                 *
 
                 #extra_comments
-                #[no_mangle]
+                #method_export
                 pub unsafe extern fn #int_method_name(#inputs) #ret_expr {
                   #panic_hook
 
                   #body
                 }
               ));
+
+              if cancellable {
+                let (cancellable_name, cancellable_export) = method_mangler.symbol(&[method_kind, &member_name, "cancellable"]);
+                let doc_comments = method.attrs.iter().filter(doc_filter);
+
+                let (ret_type, ok_body) = match &method.sig.output {
+                  syn::ReturnType::Type(_, ty) if ty.is_result() => (ty.to_c_type(), ty.convert_ret(quote!(x))),
+                  syn::ReturnType::Type(_, ty) => {
+                    let inner_type = ty.to_c_type();
+                    let inner_body = ty.convert_ret(quote!(x));
+                    (parse_quote!(::finch_gen::builtin::FinchResult<#inner_type>), quote!(::finch_gen::builtin::FinchResult::Ok(#inner_body)))
+                  },
+                  syn::ReturnType::Default => (parse_quote!(::finch_gen::builtin::FinchResult<()>), quote!(::finch_gen::builtin::FinchResult::Ok(x))),
+                };
+
+                let mut cancellable_inputs = inputs.clone();
+                cancellable_inputs.push(parse_quote!(token: *const ::finch_gen::builtin::FinchCancellationToken));
+                cancellable_inputs.push(parse_quote!(timeout_ms: u64));
+
+                functions.push(quote!(
+                  #(#doc_comments)
+                  *
+
+                  #extra_comments
+                  /// Returns `Cancelled` if `token` is cancelled or `timeout_ms` elapses before the call completes.
+                  /// `token` may be null, and a `timeout_ms` of 0 waits forever. Returns `Err` if called
+                  /// from a runtime thread, such as from inside a `___next_callback` callback.
+                  #cancellable_export
+                  pub unsafe extern fn #cancellable_name(#cancellable_inputs) -> #ret_type {
+                    #panic_hook
+
+                    let result = ::finch_gen::builtin::block_on_cancellable(token.as_ref(), timeout_ms, async {
+                      ::finch_gen::builtin::FinchResult::Ok(#call_body.await)
+                    });
+
+                    match result {
+                      Ok(Some(::finch_gen::builtin::FinchResult::Ok(x))) => #ok_body,
+                      Ok(Some(::finch_gen::builtin::FinchResult::Err(err))) => ::finch_gen::builtin::FinchResult::Err(err),
+                      Ok(Some(::finch_gen::builtin::FinchResult::Cancelled)) | Ok(None) => ::finch_gen::builtin::FinchResult::Cancelled,
+                      Err(err) => ::finch_gen::builtin::FinchResult::Err(::finch_gen::builtin::FinchString::from(err.to_string())),
+                    }
+                  }
+                ));
+              }
             }
           },
 
@@ -273,42 +694,108 @@ pub fn finch_bindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
         }
       }
 
-      let new_name = format_ident!("___finch_bindgen___{}___class___{}___type", crate_name(), name);
+      for item in input.items.iter_mut() {
+        if let syn::ImplItem::Method(method) = item {
+          attr::strip_attrs(&mut method.attrs);
+        }
+      }
+
+      if let Err(diagnostic) = mangler.check_collisions(input.self_ty.span()) {
+        return diagnostic.emit(TokenStream::from(quote!(#input)));
+      }
 
-      let item = proc_macro2::TokenStream::from(item);
+      // Checked by the compiler rather than here, so it does not depend
+      // on the struct being expanded before its impl blocks.
+      //
+      // The functions of a class take handles, which already fail to compile with the
+      // marker's error if the struct is not exported, so the assertion takes one too and
+      // the compiler reports it once rather than for every function.
+      let self_ty = &input.self_ty;
+      let (assertion, exported) = if options.value {
+        let assertion = quote_spanned!(self_ty.span() => assert_exported::<#self_ty>(););
+        (
+          quote!(
+            const _: () = {
+              fn assert_exported<T: ::finch_gen::builtin::FinchValue>() {}
 
-      let boilerplate = inject_boilerplate();
+              #[allow(dead_code)]
+              fn assert_self() {
+                #assertion
+              }
+            };
+          ),
+          quote!(::finch_gen::builtin::exported::Value::<#self_ty>),
+        )
+      } else {
+        (
+          quote!(
+            const _: () = {
+              #[allow(dead_code)]
+              fn assert_exported(_: ::finch_gen::builtin::class::Handle<#self_ty>) {}
+            };
+          ),
+          quote!(::finch_gen::builtin::exported::Class::<#self_ty>),
+        )
+      };
+      let naming_key = mangler.key();
+      let interface_hash = abi::make_interface_hash(&mangler, &functions);
+
+      // Getters and setters are exported with the struct, so a method exported under the
+      // same symbol, such as `get_x` beside a getter for `x` in the snake scheme, is caught here.
+      let symbol_assertions = mangler.exported().into_iter().map(|symbol| {
+        let message = format!("finch-gen[E0020] symbol '{}' is exported by both this impl and its struct, use #[finch_bindgen(name = \"...\")] to rename one of them, go to https://finch-gen.github.io/docs/errors/E0020 for more information", symbol);
+        quote!(
+          const _: () = {
+            use ::finch_gen::builtin::exported::NotExported;
+            assert!(!::finch_gen::builtin::str_contains(#exported::SYMBOLS, #symbol), #message);
+          };
+        )
+      });
+
+      let builtins = builtin::assert_builtins();
       let class_impl = quote!(
-        #item
+        #input
+
+        #assertion
+
+        const _: () = {
+          use ::finch_gen::builtin::exported::NotExported;
+          assert!(
+            !#exported::EXPORTED || ::finch_gen::builtin::str_eq(#exported::NAMING, #naming_key),
+            "finch-gen[E0019] impl exported with a different namespace, name, prefix or naming scheme than its struct, go to https://finch-gen.github.io/docs/errors/E0019 for more information",
+          );
+        };
+
+        #(#symbol_assertions)*
 
         #[allow(non_snake_case)]
-        impl #new_name {
+        impl #self_ty {
           #(#functions)*
         }
 
-        #boilerplate
+        #(#statics)*
+
+        #interface_hash
+
+        #builtins
       );
 
-      proc_macro::TokenStream::from(class_impl)
+      match env_diagnostic {
+        Some(diagnostic) => diagnostic.emit(TokenStream::from(class_impl)),
+        None => TokenStream::from(class_impl),
+      }
     }
 
+    syn::Item::Trait(data) => callback::make_callback(item, data, &options),
+
     _ => {
-      return Diagnostic::spanned(input.span(), DiagnosticLevel::Error, &format!("finch-gen[E0003] unexpected type for #[finch_bindgen], expected struct or impl, got '{}'", item))
+      return Diagnostic::spanned(input.span(), DiagnosticLevel::Error, &format!("finch-gen[E0003] unexpected type for #[finch_bindgen], expected struct, impl or trait, got '{}'", item))
         .note("go to https://finch-gen.github.io/docs/errors/E0003 for more information")
         .emit(item);
     }
   }
 }
 
-fn inject_boilerplate() -> proc_macro2::TokenStream {
-  let mut out = proc_macro2::TokenStream::new();
-  INJECT.call_once(|| {
-    out = builtin::make_builtin(crate_name());
-  });
-
-  out
-}
-
 fn inject_panic_hook() -> proc_macro2::TokenStream {
   quote!({
     ::finch_gen::builtin::PANIC_HOOK.call_once(|| {
@@ -320,20 +807,299 @@ fn inject_panic_hook() -> proc_macro2::TokenStream {
   };)
 }
 
+fn generic_types(segment: &syn::PathSegment) -> Vec<&syn::Type> {
+  if let syn::PathArguments::AngleBracketed(generics) = &segment.arguments {
+    generics.args.iter().filter_map(|arg| {
+      if let syn::GenericArgument::Type(ty) = arg {
+        Some(ty)
+      } else {
+        None
+      }
+    }).collect()
+  } else {
+    Vec::new()
+  }
+}
+
+/// Whether `ty` is `Arc<Self>`, passed as a handle to a class exported with `#[finch_bindgen(shared)]`.
+fn is_arc_self(ty: &syn::Type) -> bool {
+  if let syn::Type::Path(path) = ty {
+    let segment = path.path.segments.last().unwrap();
+    if segment.ident == "Arc" {
+      if let Some(syn::Type::Path(inner)) = generic_types(segment).first() {
+        return inner.path.is_ident("Self");
+      }
+    }
+  }
+
+  false
+}
+
+/// A reference lowered to a view rather than copied.
+enum Borrowed<'a> {
+  /// `&str`, passed as a `FinchStr`.
+  Str,
+  /// `&[T]` or `&mut [T]`, passed as a `FinchSlice<T>` or `FinchSliceMut<T>`.
+  Slice(&'a syn::Type, bool),
+  /// `&Path` or `&OsStr`, passed as a `FinchSlice<u8>`.
+  Os(&'a syn::Ident),
+  /// `&Class` or `&mut Class`, passed as a handle the host must not drop.
+  Class(&'a syn::Type, bool),
+}
+
+fn borrowed(ty: &syn::Type) -> Option<Borrowed<'_>> {
+  if let syn::Type::Reference(reference) = ty {
+    match &*reference.elem {
+      syn::Type::Path(path) if path.path.is_ident("str") && reference.mutability.is_none() => Some(Borrowed::Str),
+      syn::Type::Slice(slice) => Some(Borrowed::Slice(&slice.elem, reference.mutability.is_some())),
+      syn::Type::Path(path) if matches!(os_type(&reference.elem), Some(ident) if ident == "Path" || ident == "OsStr") && reference.mutability.is_none() => {
+        Some(Borrowed::Os(&path.path.segments.last().unwrap().ident))
+      },
+      syn::Type::Path(path) if path.qself.is_none() && !path.path.is_ident("str") && !is_std_type(path) => Some(Borrowed::Class(&reference.elem, reference.mutability.is_some())),
+      _ => None,
+    }
+  } else {
+    None
+  }
+}
+
+/// Primitives and standard library types, which are never exported classes, so
+/// references to them are not passed as handles.
+const STD_TYPES: &[&str] = &[
+  "bool", "char", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize", "f32", "f64",
+  "String", "Vec", "Option", "Result", "Box", "Rc", "Arc", "HashMap", "BTreeMap", "HashSet", "BTreeSet",
+  "OsString", "PathBuf", "CString", "CStr", "Duration", "SystemTime",
+];
+
+fn is_std_type(path: &syn::TypePath) -> bool {
+  let ident = &path.path.segments.last().unwrap().ident;
+  STD_TYPES.iter().any(|name| ident == name)
+}
+
+/// Rejects references which are neither views, class handles nor callbacks, such as `&String` or `&u32`.
+fn check_borrowed(ty: &syn::Type) -> Result<(), Diagnostic> {
+  let reference = match ty {
+    syn::Type::Reference(reference) if borrowed(ty).is_none() && ty.callback_trait().is_none() => reference,
+    _ => return Ok(()),
+  };
+
+  let elem = &reference.elem;
+  let help = match &**elem {
+    syn::Type::Path(path) if path.path.is_ident("String") => "take or return `&str` instead".to_string(),
+    syn::Type::Path(path) if path.path.segments.last().unwrap().ident == "Vec" => "take or return a slice instead".to_string(),
+    _ => format!("take or return '{}' by value instead", quote!(#elem)),
+  };
+
+  Err(
+    Diagnostic::spanned(ty.span(), DiagnosticLevel::Error, &format!("finch-gen[E0030] unsupported borrowed type '{}'", quote!(#ty)))
+      .note("only `&str`, slices, `&Path`, `&OsStr` and references to exported classes can be borrowed")
+      .note("go to https://finch-gen.github.io/docs/errors/E0030 for more information")
+      .help(help)
+  )
+}
+
+/// Whether `ty` contains a reference or lifetime which is not `'static`, such as `&str`
+/// or `impl Iterator<Item = u32> + '_`, so a value of this type returned by a method is
+/// borrowed from its receiver or arguments.
+fn is_borrowed(ty: &syn::Type) -> bool {
+  fn scan(tokens: proc_macro2::TokenStream) -> bool {
+    let tokens: Vec<_> = tokens.into_iter().collect();
+    tokens.iter().enumerate().any(|(i, token)| {
+      match token {
+        proc_macro2::TokenTree::Punct(punct) if punct.as_char() == '&' => {
+          match (tokens.get(i + 1), tokens.get(i + 2)) {
+            (Some(proc_macro2::TokenTree::Punct(quote)), Some(proc_macro2::TokenTree::Ident(lifetime))) => {
+              quote.as_char() != '\'' || lifetime != "static"
+            },
+            _ => true,
+          }
+        },
+        proc_macro2::TokenTree::Punct(punct) if punct.as_char() == '\'' => {
+          !matches!(tokens.get(i + 1), Some(proc_macro2::TokenTree::Ident(lifetime)) if lifetime == "static")
+        },
+        proc_macro2::TokenTree::Group(group) => scan(group.stream()),
+        _ => false,
+      }
+    })
+  }
+
+  scan(quote!(#ty))
+}
+
+/// Exports `___borrowed` for a method whose return value, such as a view or an iterator,
+/// borrows from its receiver or arguments.
+fn borrowed_marker(mangler: &naming::Mangler, method_kind: &str, member_name: &str) -> proc_macro2::TokenStream {
+  let (borrowed_name, borrowed_export) = mangler.symbol(&[method_kind, member_name, "borrowed"]);
+  quote!(
+    /// The value returned is borrowed from the receiver, or the arguments of a
+    /// static method, which must be kept alive and unchanged while it is used.
+    #borrowed_export
+    #[allow(non_upper_case_globals)]
+    pub static #borrowed_name: bool = true;
+  )
+}
+
+/// Returns `Vec` or `Bytes` if `ty` is `Vec<u8>` or `Bytes`, passed as a `FinchBytes`.
+///
+/// `Bytes` is matched like the other native types and converted through `Vec<u8>`, so finch-gen
+/// does not depend on the bytes crate. The conversion does not copy when the buffer is not shared.
+fn bytes_type(ty: &syn::Type) -> Option<&syn::Ident> {
+  if let syn::Type::Path(path) = ty {
+    let segment = path.path.segments.last().unwrap();
+    if native::type_name(&path.path) == Some("Bytes") && segment.arguments.is_empty() {
+      return Some(&segment.ident);
+    } else if segment.ident == "Vec" {
+      if let Some(syn::Type::Path(inner)) = generic_types(segment).first() {
+        if inner.path.is_ident("u8") {
+          return Some(&segment.ident);
+        }
+      }
+    }
+  }
+
+  None
+}
+
+/// Returns the last segment of `OsString`, `PathBuf`, `OsStr` or `Path`, passed as bytes.
+fn os_type(ty: &syn::Type) -> Option<&syn::Ident> {
+  if let syn::Type::Path(path) = ty {
+    let segment = path.path.segments.last().unwrap();
+    if matches!(native::type_name(&path.path), Some("OsString" | "PathBuf" | "OsStr" | "Path")) && segment.arguments.is_empty() {
+      return Some(&segment.ident);
+    }
+  }
+
+  None
+}
+
+/// Whether converting `ty` can fail, when passed directly or in an `Option`, `Result`,
+/// tuple, map, set or array: strings received in the C and UTF-16 modes, C strings returned,
+/// and URLs and timestamps received. Types converted with `FinchConvert` are found by `convert_types`.
+fn is_fallible(ty: &syn::Type, arg: bool) -> bool {
+  match ty {
+    syn::Type::Path(path) => {
+      let segment = path.path.segments.last().unwrap();
+      match strings::string_type(ty) {
+        Some(string_type) => arg || string_type == "FinchCString",
+        None if matches!(native::native_type(ty), Some("url" | "system_time" | "date_time")) => arg,
+        None if CONTAINERS.iter().any(|name| segment.ident == name) => {
+          generic_types(segment).iter().any(|inner| is_fallible(inner, arg))
+        },
+        None => false,
+      }
+    },
+    syn::Type::Tuple(tuple) => tuple.elems.iter().any(|elem| is_fallible(elem, arg)),
+    syn::Type::Array(array) => is_fallible(&array.elem, arg),
+    _ => false,
+  }
+}
+
+const CONTAINERS: &[&str] = &["Option", "Result", "HashMap", "BTreeMap", "HashSet", "BTreeSet"];
+
+/// Collects the types received through `FinchConvert` in `ty`, whose conversion can only
+/// fail if their `FinchConvert::FALLIBLE` is true, which is not known to the macro.
+fn convert_types(ty: &syn::Type, types: &mut Vec<syn::Type>) {
+  match ty {
+    syn::Type::Path(path) => {
+      let segment = path.path.segments.last().unwrap();
+      if strings::string_type(ty).is_some() || native::native_type(ty).is_some() {
+        return;
+      }
+
+      if CONTAINERS.iter().any(|name| segment.ident == name) {
+        generic_types(segment).iter().for_each(|inner| convert_types(inner, types));
+      } else if is_convert(&ty.to_c_type()) {
+        types.push(ty.clone());
+      }
+    },
+    syn::Type::Tuple(tuple) => tuple.elems.iter().for_each(|elem| convert_types(elem, types)),
+    syn::Type::Array(array) => convert_types(&array.elem, types),
+    _ => {},
+  }
+}
+
+/// Whether `c_type` is `<T as FinchConvert>::Ffi`, rather than a builtin, a class handle
+/// or a callback vtable.
+fn is_convert(c_type: &syn::Type) -> bool {
+  match c_type {
+    syn::Type::Path(path) => {
+      matches!(&path.qself, Some(qself) if qself.position > 0)
+        && path.path.segments.iter().rev().nth(1).is_some_and(|segment| segment.ident == "FinchConvert")
+    },
+    _ => false,
+  }
+}
+
+/// Whether converting `ty` may return early with an error, so it must be done in a
+/// function returning a `FinchResult`.
+fn may_fail(ty: &syn::Type, arg: bool) -> bool {
+  let mut types = Vec::new();
+  if arg {
+    convert_types(ty, &mut types);
+  }
+  is_fallible(ty, arg) || !types.is_empty()
+}
+
 trait ToCType {
+  fn is_result(&self) -> bool;
+  fn callback_trait(&self) -> Option<&syn::Path>;
   fn to_c_type(&self) -> syn::Type;
   fn convert_arg(&self, body: proc_macro2::TokenStream) -> proc_macro2::TokenStream;
   fn convert_ret(&self, body: proc_macro2::TokenStream) -> proc_macro2::TokenStream;
 }
 
 impl ToCType for syn::Type {
+  fn is_result(&self) -> bool {
+    if let syn::Type::Path(path) = self {
+      path.path.segments.last().unwrap().ident == "Result"
+    } else {
+      false
+    }
+  }
+
+  fn callback_trait(&self) -> Option<&syn::Path> {
+    let ty = match self {
+      syn::Type::Path(path) => {
+        let segment = path.path.segments.last().unwrap();
+        match segment.ident.to_string().as_str() {
+          "Box" | "Arc" | "Rc" => {
+            if let syn::PathArguments::AngleBracketed(generics) = &segment.arguments {
+              if let Some(syn::GenericArgument::Type(ty)) = generics.args.first() {
+                ty
+              } else {
+                return None;
+              }
+            } else {
+              return None;
+            }
+          },
+          _ => return None,
+        }
+      },
+      syn::Type::Reference(reference) => &*reference.elem,
+      ty => ty,
+    };
+
+    match ty {
+      syn::Type::TraitObject(obj) => callback::trait_bound(&obj.bounds),
+      syn::Type::ImplTrait(obj) => callback::trait_bound(&obj.bounds),
+      _ => None,
+    }
+  }
+
   fn to_c_type(&self) -> syn::Type {
     match self.clone() {
       syn::Type::Path(path) => {
-        let ident = path.path.segments.first().unwrap().ident.clone();
+        let ident = path.path.segments.last().unwrap().ident.clone();
         let ty_name = ident.to_string();
   
         match ty_name.as_str() {
+          _ if native::c_type(self).is_some() => native::c_type(self).unwrap(),
+
+          _ if strings::string_type(self).is_some() => parse_quote!(#self),
+
+          _ if bytes_type(self).is_some() || os_type(self).is_some() => parse_quote!(::finch_gen::builtin::FinchBytes),
+
           "bool" | "char" | "u8" | "u16" | "u32" | "u64" | "usize"|
           "i8" | "i16" | "i32" | "i64" | "isize" | "f32" | "f64" |
           "c_void" | "c_char" | "c_schar" | "c_uchar" | "c_float" |
@@ -343,12 +1109,12 @@ impl ToCType for syn::Type {
           "size_t" |" int8_t" | "int16_t" | "int32_t" | "int64_t" |
           "intptr_t" | "ssize_t" | "ptrdiff_t" => parse_quote!(#self),
   
-          "Self" => parse_quote!(*mut #self),
+          "Self" => parse_quote!(::finch_gen::builtin::class::Handle<Self>),
   
           "String" => parse_quote!(::finch_gen::builtin::FinchString),
   
           "Option" => {
-            if let syn::PathArguments::AngleBracketed(generics) = &path.path.segments.first().unwrap().arguments {
+            if let syn::PathArguments::AngleBracketed(generics) = &path.path.segments.last().unwrap().arguments {
               if let syn::GenericArgument::Type(ty) = generics.args.first().unwrap() {
                 let inner_type = ty.to_c_type();
                 parse_quote!(::finch_gen::builtin::FinchOption<#inner_type>)
@@ -361,7 +1127,7 @@ impl ToCType for syn::Type {
           },
   
           "Result" => {
-            if let syn::PathArguments::AngleBracketed(generics) = &path.path.segments.first().unwrap().arguments {
+            if let syn::PathArguments::AngleBracketed(generics) = &path.path.segments.last().unwrap().arguments {
               if let syn::GenericArgument::Type(ty) = generics.args.first().unwrap() {
                 let inner_type = ty.to_c_type();
                 parse_quote!(::finch_gen::builtin::FinchResult<#inner_type>)
@@ -373,9 +1139,66 @@ impl ToCType for syn::Type {
             }
           },
   
-          _ => parse_quote!(#self),
+          "HashMap" | "BTreeMap" => {
+            let args = generic_types(path.path.segments.last().unwrap());
+            if args.len() >= 2 {
+              let key_type = args[0].to_c_type();
+              let value_type = args[1].to_c_type();
+              parse_quote!(::finch_gen::builtin::FinchMap<#key_type, #value_type>)
+            } else {
+              parse_quote!(())
+            }
+          },
+
+          "HashSet" | "BTreeSet" => {
+            if let Some(ty) = generic_types(path.path.segments.last().unwrap()).first() {
+              let inner_type = ty.to_c_type();
+              parse_quote!(::finch_gen::builtin::FinchSet<#inner_type>)
+            } else {
+              parse_quote!(())
+            }
+          },
+
+          "Box" | "Arc" | "Rc" => {
+            if let Some(trait_path) = self.callback_trait() {
+              callback::callback_c_type(trait_path)
+            } else if is_arc_self(self) {
+              parse_quote!(::finch_gen::builtin::class::Handle<Self>)
+            } else {
+              parse_quote!(<#self as ::finch_gen::builtin::FinchConvert>::Ffi)
+            }
+          },
+
+          _ => parse_quote!(<#self as ::finch_gen::builtin::FinchConvert>::Ffi),
         }
       },
+
+      syn::Type::ImplTrait(_) | syn::Type::Reference(_) => {
+        if let Some(trait_path) = self.callback_trait() {
+          callback::callback_c_type(trait_path)
+        } else {
+          match borrowed(self) {
+            Some(Borrowed::Str) => parse_quote!(::finch_gen::builtin::FinchStr),
+            Some(Borrowed::Slice(elem, false)) => parse_quote!(::finch_gen::builtin::FinchSlice<#elem>),
+            Some(Borrowed::Slice(elem, true)) => parse_quote!(::finch_gen::builtin::FinchSliceMut<#elem>),
+            Some(Borrowed::Os(_)) => parse_quote!(::finch_gen::builtin::FinchSlice<u8>),
+            Some(Borrowed::Class(elem, _)) => parse_quote!(::finch_gen::builtin::class::Handle<#elem>),
+            None => parse_quote!(#self),
+          }
+        }
+      },
+
+      syn::Type::Tuple(tuple) if !tuple.elems.is_empty() && tuple.elems.len() <= MAX_TUPLE_LEN => {
+        let tuple_name = format_ident!("FinchTuple{}", tuple.elems.len());
+        let elems = tuple.elems.iter().map(|ty| ty.to_c_type());
+        parse_quote!(::finch_gen::builtin::#tuple_name<#(#elems),*>)
+      },
+
+      syn::Type::Array(array) => {
+        let elem = array.elem.to_c_type();
+        let len = &array.len;
+        parse_quote!(::finch_gen::builtin::FinchArray<#elem, #len>)
+      },
   
       _ => parse_quote!(#self),
     }
@@ -384,10 +1207,48 @@ impl ToCType for syn::Type {
   fn convert_arg(&self, body: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
     match self.clone() {
       syn::Type::Path(path) => {
-        let ident = path.path.segments.first().unwrap().ident.clone();
+        let ident = path.path.segments.last().unwrap().ident.clone();
         let ty_name = ident.to_string();
   
         match ty_name.as_str() {
+          _ if strings::string_type(self).is_some() => {
+            let string_type = strings::string_type(self).unwrap();
+            let convert = match string_type.to_string().as_str() {
+              "FinchCChars" => quote!(::finch_gen::builtin::FinchCString::from_ptr(#body)),
+              "FinchStr16" => quote!(::finch_gen::builtin::FinchStr16::to_string(#body)),
+              _ => quote!(::finch_gen::builtin::#string_type::into_string(#body)),
+            };
+
+            let string = quote!(match #convert {
+              Ok(x) => x,
+              Err(err) => return ::finch_gen::builtin::FinchResult::Err(err),
+            });
+
+            if string_type == "FinchStr16" {
+              quote!(&*#string)
+            } else {
+              string
+            }
+          },
+
+          _ if native::c_type(self).is_some() => native::convert_arg(self, body),
+
+          _ if os_type(self).is_some() => {
+            if os_type(self).unwrap() == "PathBuf" {
+              quote!(::std::path::PathBuf::from(::finch_gen::builtin::os::from_bytes(#body)))
+            } else {
+              quote!(::finch_gen::builtin::os::from_bytes(#body))
+            }
+          },
+
+          _ if bytes_type(self).is_some() => {
+            if bytes_type(self).unwrap() == "Vec" {
+              quote!(::finch_gen::builtin::FinchBytes::into_vec(#body))
+            } else {
+              quote!(<#self>::from(::finch_gen::builtin::FinchBytes::into_vec(#body)))
+            }
+          },
+
           "bool" | "char" | "u8" | "u16" | "u32" | "u64" | "usize"|
           "i8" | "i16" | "i32" | "i64" | "isize" | "f32" | "f64" |
           "c_void" | "c_char" | "c_schar" | "c_uchar" | "c_float" |
@@ -397,12 +1258,12 @@ impl ToCType for syn::Type {
           "size_t" |" int8_t" | "int16_t" | "int32_t" | "int64_t" |
           "intptr_t" | "ssize_t" | "ptrdiff_t" => body,
   
-          "Self" => quote!(Box::into_raw(Box::new(#body))),
+          "Self" => quote!(::finch_gen::builtin::class::take::<Self>(#body)),
   
           "String" => quote!(*Box::from_raw(::std::mem::ManuallyDrop::new(#body).string)),
 
           "Option" => {
-            if let syn::PathArguments::AngleBracketed(generics) = &path.path.segments.first().unwrap().arguments {
+            if let syn::PathArguments::AngleBracketed(generics) = &path.path.segments.last().unwrap().arguments {
               if let syn::GenericArgument::Type(ty) = generics.args.first().unwrap() {
                 let inner_body = ty.convert_arg(quote!(x));
                 quote!({
@@ -427,16 +1288,126 @@ impl ToCType for syn::Type {
               )
             }
           },
+
+          "HashMap" | "BTreeMap" => {
+            let args = generic_types(path.path.segments.last().unwrap());
+            if args.len() >= 2 {
+              let key_body = args[0].convert_arg(quote!(k));
+              let value_body = args[1].convert_arg(quote!(v));
+              // Converted in a loop rather than a closure, so conversions may return early.
+              quote!({
+                let mut entries = ::std::vec::Vec::new();
+                for (k, v) in ::finch_gen::builtin::FinchMap::into_entries(#body) {
+                  entries.push((#key_body, #value_body));
+                }
+                entries.into_iter().collect()
+              })
+            } else {
+              proc_macro2::TokenStream::from(
+                Diagnostic::spanned(self.span(), DiagnosticLevel::Error, &format!("finch-gen[E0007] expected generics for {}", ty_name))
+                  .note("go to https://finch-gen.github.io/docs/errors/E0007 for more information")
+                  .emit(TokenStream::new()),
+              )
+            }
+          },
+
+          "HashSet" | "BTreeSet" => {
+            if let Some(ty) = generic_types(path.path.segments.last().unwrap()).first() {
+              let inner_body = ty.convert_arg(quote!(x));
+              quote!({
+                let mut items = ::std::vec::Vec::new();
+                for x in ::finch_gen::builtin::FinchSet::into_items(#body) {
+                  items.push(#inner_body);
+                }
+                items.into_iter().collect()
+              })
+            } else {
+              proc_macro2::TokenStream::from(
+                Diagnostic::spanned(self.span(), DiagnosticLevel::Error, &format!("finch-gen[E0007] expected generics for {}", ty_name))
+                  .note("go to https://finch-gen.github.io/docs/errors/E0007 for more information")
+                  .emit(TokenStream::new()),
+              )
+            }
+          },
+
+          "Arc" if is_arc_self(self) => quote!(::finch_gen::builtin::class::to_arc::<Self>(#body)),
+
+          "Box" | "Arc" | "Rc" if self.callback_trait().is_some() => {
+            let body = callback::callback_arg(self.callback_trait().unwrap(), body);
+            match ty_name.as_str() {
+              "Box" => quote!(::std::boxed::Box::new(#body)),
+              "Arc" => quote!(::std::sync::Arc::new(#body)),
+              _ => quote!(::std::rc::Rc::new(#body)),
+            }
+          },
   
-          _ => {
-            proc_macro2::TokenStream::from(
-              Diagnostic::spanned(self.span(), DiagnosticLevel::Error, &format!("finch-gen[E0004] unsupported type '{}'", quote!(#self)))
-                .note("go to https://finch-gen.github.io/docs/errors/E0004 for more information")
-                .emit(TokenStream::new()),
-            )
-          }
+          _ => quote!(match <#self as ::finch_gen::builtin::FinchConvert>::from_ffi(#body) {
+            Ok(x) => x,
+            Err(err) => return ::finch_gen::builtin::FinchResult::Err(err),
+          }),
+        }
+      },
+
+      syn::Type::ImplTrait(_) if self.callback_trait().is_some() => callback::callback_arg(self.callback_trait().unwrap(), body),
+
+      syn::Type::Reference(reference) if self.callback_trait().is_some() => {
+        let body = callback::callback_arg(self.callback_trait().unwrap(), body);
+        if reference.mutability.is_some() {
+          quote!(&mut #body)
+        } else {
+          quote!(&#body)
+        }
+      },
+
+      syn::Type::Reference(_) if borrowed(self).is_some() => {
+        match borrowed(self).unwrap() {
+          Borrowed::Str => quote!((#body).as_str()),
+          Borrowed::Slice(_, false) => quote!((#body).as_slice()),
+          Borrowed::Slice(_, true) => quote!((#body).as_mut_slice()),
+          Borrowed::Os(ident) if ident == "Path" => quote!(::std::path::Path::new(&*::finch_gen::builtin::os::from_slice(#body))),
+          Borrowed::Os(_) => quote!(&*::finch_gen::builtin::os::from_slice(#body)),
+          Borrowed::Class(elem, false) => quote!(&*::finch_gen::builtin::class::get::<#elem>(#body)),
+          Borrowed::Class(elem, true) => quote!(&mut *::finch_gen::builtin::class::get_mut::<#elem>(#body)),
+        }
+      },
+
+      syn::Type::Tuple(tuple) if !tuple.elems.is_empty() && tuple.elems.len() <= MAX_TUPLE_LEN => {
+        let elems = tuple.elems.iter().enumerate().map(|(i, ty)| {
+          let field = format_ident!("_{}", i);
+          ty.convert_arg(quote!(x.#field))
+        });
+        quote!({
+          let x = #body;
+          (#(#elems,)*)
+        })
+      },
+
+      syn::Type::Array(array) => {
+        let inner_body = array.elem.convert_arg(quote!(x));
+        if may_fail(&array.elem, true) {
+          let len = &array.len;
+          quote!({
+            let mut items = ::std::vec::Vec::new();
+            for x in (#body).data {
+              items.push(#inner_body);
+            }
+            match <[_; #len] as ::std::convert::TryFrom<::std::vec::Vec<_>>>::try_from(items) {
+              Ok(x) => x,
+              Err(_) => unreachable!(),
+            }
+          })
+        } else {
+          quote!((#body).data.map(|x| #inner_body))
         }
       },
+
+      syn::Type::Tuple(tuple) if tuple.elems.len() > MAX_TUPLE_LEN => {
+        proc_macro2::TokenStream::from(
+          Diagnostic::spanned(self.span(), DiagnosticLevel::Error, &format!("finch-gen[E0012] tuples with more than {} elements are not supported", MAX_TUPLE_LEN))
+            .note("go to https://finch-gen.github.io/docs/errors/E0012 for more information")
+            .emit(TokenStream::new()),
+        )
+      },
   
       _ => {
           proc_macro2::TokenStream::from(
@@ -451,10 +1422,39 @@ impl ToCType for syn::Type {
   fn convert_ret(&self, body: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
     match self.clone() {
       syn::Type::Path(path) => {
-        let ident = path.path.segments.first().unwrap().ident.clone();
+        let ident = path.path.segments.last().unwrap().ident.clone();
         let ty_name = ident.to_string();
   
         match ty_name.as_str() {
+          _ if strings::string_type(self).is_some() => {
+            if strings::string_type(self).unwrap() == "FinchString16" {
+              quote!(::finch_gen::builtin::FinchString16::encode(&#body))
+            } else {
+              quote!(match ::finch_gen::builtin::FinchCString::new(#body) {
+                Ok(x) => x,
+                Err(err) => return ::finch_gen::builtin::FinchResult::Err(err),
+              })
+            }
+          },
+
+          _ if native::c_type(self).is_some() => native::convert_ret(self, body),
+
+          _ if os_type(self).is_some() => {
+            if os_type(self).unwrap() == "PathBuf" {
+              quote!(::finch_gen::builtin::os::into_bytes((#body).into_os_string()))
+            } else {
+              quote!(::finch_gen::builtin::os::into_bytes(#body))
+            }
+          },
+
+          _ if bytes_type(self).is_some() => {
+            if bytes_type(self).unwrap() == "Vec" {
+              quote!(::finch_gen::builtin::FinchBytes::from(#body))
+            } else {
+              quote!(::finch_gen::builtin::FinchBytes::from(::std::vec::Vec::<u8>::from(#body)))
+            }
+          },
+
           "bool" | "char" | "u8" | "u16" | "u32" | "u64" | "usize"|
           "i8" | "i16" | "i32" | "i64" | "isize" | "f32" | "f64" |
           "c_void" | "c_char" | "c_schar" | "c_uchar" | "c_float" |
@@ -464,12 +1464,12 @@ impl ToCType for syn::Type {
           "size_t" |" int8_t" | "int16_t" | "int32_t" | "int64_t" |
           "intptr_t" | "ssize_t" | "ptrdiff_t" => body,
   
-          "Self" => quote!(Box::into_raw(Box::new(#body))),
+          "Self" => quote!(::finch_gen::builtin::class::into_handle::<Self>(#body)),
   
           "String" => quote!(::finch_gen::builtin::FinchString::from(#body)),
   
           "Option" => {
-            if let syn::PathArguments::AngleBracketed(generics) = &path.path.segments.first().unwrap().arguments {
+            if let syn::PathArguments::AngleBracketed(generics) = &path.path.segments.last().unwrap().arguments {
               if let syn::GenericArgument::Type(ty) = generics.args.first().unwrap() {
                 let inner_body = ty.convert_ret(quote!(x));
                 quote!({
@@ -496,7 +1496,7 @@ impl ToCType for syn::Type {
           },
   
           "Result" => {
-            if let syn::PathArguments::AngleBracketed(generics) = &path.path.segments.first().unwrap().arguments {
+            if let syn::PathArguments::AngleBracketed(generics) = &path.path.segments.last().unwrap().arguments {
               if let syn::GenericArgument::Type(ty) = generics.args.first().unwrap() {
                 let inner_body = ty.convert_ret(quote!(x));
                 quote!({
@@ -521,17 +1521,82 @@ impl ToCType for syn::Type {
               )
             }
           },
+
+          "Arc" if is_arc_self(self) => quote!(::finch_gen::builtin::class::from_arc::<Self>(#body)),
+
+          "HashMap" | "BTreeMap" => {
+            let args = generic_types(path.path.segments.last().unwrap());
+            if args.len() >= 2 {
+              let key_body = args[0].convert_ret(quote!(k));
+              let value_body = args[1].convert_ret(quote!(v));
+              quote!(::finch_gen::builtin::FinchMap::from_entries((#body).into_iter().map(|(k, v)| (#key_body, #value_body))))
+            } else {
+              proc_macro2::TokenStream::from(
+                Diagnostic::spanned(self.span(), DiagnosticLevel::Error, &format!("finch-gen[E0007] expected generics for {}", ty_name))
+                  .note("go to https://finch-gen.github.io/docs/errors/E0007 for more information")
+                  .emit(TokenStream::new()),
+              )
+            }
+          },
+
+          "HashSet" | "BTreeSet" => {
+            if let Some(ty) = generic_types(path.path.segments.last().unwrap()).first() {
+              let inner_body = ty.convert_ret(quote!(x));
+              quote!(::finch_gen::builtin::FinchSet::from_items((#body).into_iter().map(|x| #inner_body)))
+            } else {
+              proc_macro2::TokenStream::from(
+                Diagnostic::spanned(self.span(), DiagnosticLevel::Error, &format!("finch-gen[E0007] expected generics for {}", ty_name))
+                  .note("go to https://finch-gen.github.io/docs/errors/E0007 for more information")
+                  .emit(TokenStream::new()),
+              )
+            }
+          },
   
-          _ => {
-            proc_macro2::TokenStream::from(
-              Diagnostic::spanned(self.span(), DiagnosticLevel::Error, &format!("finch-gen[E0004] unsupported type '{}'", quote!(#self)))
-                .note("go to https://finch-gen.github.io/docs/errors/E0004 for more information")
-                .emit(TokenStream::new()),
-            )
-          }
+          _ => quote!(::finch_gen::builtin::FinchConvert::to_ffi(#body)),
+        }
+      },
+
+      syn::Type::Reference(_) if borrowed(self).is_some() => {
+        match borrowed(self).unwrap() {
+          Borrowed::Str => quote!(::finch_gen::builtin::FinchStr::from(#body)),
+          Borrowed::Slice(_, false) => quote!(::finch_gen::builtin::FinchSlice::from(#body)),
+          Borrowed::Slice(_, true) => quote!(::finch_gen::builtin::FinchSliceMut::from(#body)),
+          Borrowed::Os(_) => quote!(::finch_gen::builtin::os::as_slice((#body).as_ref())),
+          Borrowed::Class(elem, _) => quote!(::finch_gen::builtin::class::borrow::<#elem>(#body)),
         }
       },
   
+      syn::Type::Tuple(tuple) if !tuple.elems.is_empty() && tuple.elems.len() <= MAX_TUPLE_LEN => {
+        let tuple_name = format_ident!("FinchTuple{}", tuple.elems.len());
+        let names: Vec<_> = (0..tuple.elems.len()).map(|i| format_ident!("x{}", i)).collect();
+        let fields = tuple.elems.iter().zip(&names).enumerate().map(|(i, (ty, name))| {
+          let field = format_ident!("_{}", i);
+          let inner_body = ty.convert_ret(quote!(#name));
+          quote!(#field: #inner_body)
+        });
+        quote!({
+          let (#(#names,)*) = #body;
+          ::finch_gen::builtin::#tuple_name {
+            #(#fields),*
+          }
+        })
+      },
+
+      syn::Type::Array(array) => {
+        let inner_body = array.elem.convert_ret(quote!(x));
+        quote!(::finch_gen::builtin::FinchArray {
+          data: (#body).map(|x| #inner_body),
+        })
+      },
+
+      syn::Type::Tuple(tuple) if tuple.elems.len() > MAX_TUPLE_LEN => {
+        proc_macro2::TokenStream::from(
+          Diagnostic::spanned(self.span(), DiagnosticLevel::Error, &format!("finch-gen[E0012] tuples with more than {} elements are not supported", MAX_TUPLE_LEN))
+            .note("go to https://finch-gen.github.io/docs/errors/E0012 for more information")
+            .emit(TokenStream::new()),
+        )
+      },
+
       _ => {
           proc_macro2::TokenStream::from(
             Diagnostic::spanned(self.span(), DiagnosticLevel::Error, &format!("finch-gen[E0004] unsupported type '{}'", quote!(#self)))
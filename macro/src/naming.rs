@@ -0,0 +1,254 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use quote::{quote, format_ident};
+
+use crate::{attr::Options, crate_name};
+use crate::diagnostic::{Diagnostic, DiagnosticLevel};
+
+/// How the C symbol names of the generated functions are built, set with
+/// `#[finch_bindgen(naming = "...")]`.
+#[derive(Clone, PartialEq)]
+pub enum Scheme {
+  /// `___finch_bindgen___{crate}___class___Config___static___new`, the default.
+  Finch,
+  /// `{crate}_config_new`.
+  Snake,
+  /// A template using `{prefix}`, `{namespace}`, `{kind}`, `{name}` and `{member}`,
+  /// such as `"{prefix}_{name}_{member}"`. `{name}` and `{member}` are required, so
+  /// members of different structs are not exported under the same symbol.
+  Template(String),
+}
+
+impl Scheme {
+  pub fn parse(value: &str) -> Option<Self> {
+    match value {
+      "finch" => Some(Self::Finch),
+      "snake" => Some(Self::Snake),
+      template => {
+        let mut rest = template;
+        let mut literal = String::new();
+
+        while let Some(start) = rest.find('{') {
+          let end = start + rest[start..].find('}')?;
+          match &rest[start + 1..end] {
+            "prefix" | "namespace" | "kind" | "name" | "member" => {},
+            _ => return None,
+          }
+
+          literal += &rest[..start];
+          rest = &rest[end + 1..];
+        }
+        literal += rest;
+
+        if template.contains("{name}") && template.contains("{member}") && literal.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+          Some(Self::Template(template.to_string()))
+        } else {
+          None
+        }
+      }
+    }
+  }
+
+  fn key(&self) -> &str {
+    match self {
+      Self::Finch => "finch",
+      Self::Snake => "snake",
+      Self::Template(template) => template,
+    }
+  }
+}
+
+/// Builds the names of the symbols exported for a struct, impl or trait.
+///
+/// The Rust name of every generated item always follows the finch scheme,
+/// `___finch_bindgen___{crate}[___namespace___{a__b}]___{kind}___{Name}___{member}`,
+/// which doubles as the metadata read by the generators. Other schemes only
+/// change the C symbol, through `#[export_name]`.
+#[derive(Clone)]
+pub struct Mangler {
+  prefix: String,
+  namespace: Vec<String>,
+  kind: String,
+  name: String,
+  scheme: Scheme,
+  exported: Rc<RefCell<Vec<String>>>,
+}
+
+impl Mangler {
+  pub fn new(kind: &str, name: &syn::Ident, options: &Options) -> Result<Self, Diagnostic> {
+    let namespace = options.namespace.as_ref().map(|namespace| {
+      namespace.segments.iter().map(|segment| segment.ident.to_string()).collect()
+    }).unwrap_or_default();
+
+    let prefix = match &options.prefix {
+      Some(prefix) => prefix.to_string(),
+      None => crate_name()?,
+    };
+
+    Ok(Self {
+      prefix,
+      namespace,
+      kind: kind.to_string(),
+      name: member_name(name, options),
+      scheme: options.naming.clone().unwrap_or(Scheme::Finch),
+      exported: Rc::new(RefCell::new(Vec::new())),
+    })
+  }
+
+  /// Builds the names of the builtins exported by `export_builtins!()` for the builtin
+  /// type `name`, such as `FinchString`, or for none when `name` is empty, as for `abi_version`.
+  pub fn builtin(name: &str, options: &Options) -> Result<Self, Diagnostic> {
+    let prefix = match &options.prefix {
+      Some(prefix) => prefix.to_string(),
+      None => crate_name()?,
+    };
+
+    Ok(Self {
+      prefix,
+      namespace: Vec::new(),
+      kind: "builtin".to_string(),
+      name: name.to_string(),
+      scheme: options.naming.clone().unwrap_or(Scheme::Finch),
+      exported: Rc::new(RefCell::new(Vec::new())),
+    })
+  }
+
+  /// Applies the `prefix` and `naming` options of a method or field, sharing
+  /// the exported names so collisions are still detected.
+  pub fn with_options(&self, options: &Options) -> Self {
+    let mut mangler = self.clone();
+    if let Some(prefix) = &options.prefix {
+      mangler.prefix = prefix.to_string();
+    }
+    if let Some(scheme) = &options.naming {
+      mangler.scheme = scheme.clone();
+    }
+    mangler
+  }
+
+  fn finch_prefix(&self) -> String {
+    let mut prefix = format!("___finch_bindgen___{}", self.prefix);
+    if !self.namespace.is_empty() {
+      prefix += &format!("___namespace___{}", self.namespace.join("__"));
+    }
+    if self.name.is_empty() {
+      prefix + &format!("___{}", self.kind)
+    } else {
+      prefix + &format!("___{}___{}", self.kind, self.name)
+    }
+  }
+
+  /// Identifies the options affecting symbol names, so an impl can be
+  /// checked against its struct.
+  pub fn key(&self) -> String {
+    format!("{} {}", self.finch_prefix(), self.scheme.key())
+  }
+
+  /// Returns the Rust name of the item generated for `member`, such as `["method", "len"]`.
+  pub fn ident(&self, member: &[&str]) -> syn::Ident {
+    format_ident!("{}___{}", self.finch_prefix(), member.join("___"))
+  }
+
+  /// Returns the Rust name of the function or static generated for `member`,
+  /// and the `#[no_mangle]` or `#[export_name]` attribute exporting it.
+  pub fn symbol(&self, member: &[&str]) -> (syn::Ident, proc_macro2::TokenStream) {
+    let ident = self.ident(member);
+
+    let (symbol, attr) = match &self.scheme {
+      Scheme::Finch => (ident.to_string(), quote!(#[no_mangle])),
+      scheme => {
+        let symbol = self.render(scheme, member);
+        (symbol.clone(), quote!(#[export_name = #symbol]))
+      },
+    };
+
+    self.exported.borrow_mut().push(symbol);
+    (ident, attr)
+  }
+
+  fn render(&self, scheme: &Scheme, member: &[&str]) -> String {
+    let prefix = self.prefix.clone();
+    let member = short_member(member);
+
+    match scheme {
+      Scheme::Finch => unreachable!(),
+      Scheme::Snake => {
+        let mut parts = vec![prefix];
+        parts.extend(self.namespace.iter().map(|segment| snake_case(segment)));
+        if !self.name.is_empty() {
+          parts.push(snake_case(&self.name));
+        }
+        parts.push(member);
+        parts.join("_")
+      },
+      Scheme::Template(template) => {
+        template
+          .replace("{prefix}", &prefix)
+          .replace("{namespace}", &self.namespace.join("_"))
+          .replace("{kind}", &self.kind)
+          .replace("{name}", &self.name)
+          .replace("{member}", &member)
+      },
+    }
+  }
+
+  /// The symbols exported through this mangler so far.
+  pub fn exported(&self) -> Vec<String> {
+    self.exported.borrow().clone()
+  }
+
+  /// Fails if any symbol was exported more than once through this mangler,
+  /// such as two methods renamed to the same name.
+  pub fn check_collisions(&self, span: proc_macro2::Span) -> Result<(), Diagnostic> {
+    let exported = self.exported.borrow();
+    let collision = exported.iter().enumerate().find(|(i, symbol)| exported[..*i].contains(symbol));
+
+    if let Some((_, symbol)) = collision {
+      Err(
+        Diagnostic::spanned(span, DiagnosticLevel::Error, &format!("finch-gen[E0020] symbol '{}' is exported more than once", symbol))
+          .note("go to https://finch-gen.github.io/docs/errors/E0020 for more information")
+          .help("use #[finch_bindgen(name = \"...\")] to rename one of them")
+      )
+    } else {
+      Ok(())
+    }
+  }
+}
+
+/// The exported name of a method or field, from `#[finch_bindgen(name = "...")]`.
+pub fn member_name(ident: &syn::Ident, options: &Options) -> String {
+  options.name.as_ref().unwrap_or(ident).to_string()
+}
+
+/// Shortens a member such as `["static", "new"]` or `["getter", "x"]` to
+/// `new` or `get_x` for the readable schemes.
+fn short_member(member: &[&str]) -> String {
+  let parts: Vec<_> = member.iter().enumerate().filter_map(|(i, part)| {
+    match (i, *part) {
+      (0, "method") | (0, "static") | (0, "method_consume") | (0, "iterator") | (0, "stream") if member.len() > 1 => None,
+      (0, "getter") => Some("get"),
+      (0, "setter") => Some("set"),
+      (_, part) => Some(part),
+    }
+  }).collect();
+
+  parts.join("_")
+}
+
+fn snake_case(name: &str) -> String {
+  let chars: Vec<_> = name.chars().collect();
+  let mut snake = String::new();
+
+  for (i, c) in chars.iter().enumerate() {
+    if c.is_uppercase() && i > 0 {
+      let prev = chars[i - 1];
+      let next_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+      if prev.is_lowercase() || prev.is_ascii_digit() || (prev.is_uppercase() && next_lower) {
+        snake.push('_');
+      }
+    }
+    snake.extend(c.to_lowercase());
+  }
+
+  snake
+}
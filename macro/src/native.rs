@@ -0,0 +1,212 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::parse_quote;
+
+use crate::diagnostic::{Diagnostic, DiagnosticLevel};
+use crate::naming::Mangler;
+
+/// Returns the kind of `ty` if it is a std or third-party type passed as a builtin,
+/// which is recorded in the metadata so generators can map it to a native host type.
+///
+/// These types are matched by name, such as `Duration` imported with a `use`, or by their
+/// full path, such as `std::time::Duration`. Any other path, such as `chrono::Duration` or
+/// `crate::Duration`, falls through to `FinchConvert`.
+pub fn native_type(ty: &syn::Type) -> Option<&'static str> {
+  match ty {
+    syn::Type::Path(path) if path.qself.is_none() => {
+      let segment = path.path.segments.last().unwrap();
+      let kind = match type_name(&path.path)? {
+        "Duration" => "duration",
+        "SystemTime" => "system_time",
+        "DateTime" => return date_time(segment),
+        "Uuid" => "uuid",
+        "Url" => "url",
+        "PathBuf" => "path",
+        "OsString" => "os_string",
+        _ => return None,
+      };
+
+      if segment.arguments.is_empty() {
+        Some(kind)
+      } else {
+        None
+      }
+    },
+    syn::Type::Reference(reference) => {
+      match &*reference.elem {
+        syn::Type::Path(path) if path.qself.is_none() && path.path.segments.last().unwrap().arguments.is_empty() => {
+          match type_name(&path.path)? {
+            "Path" => Some("path"),
+            "OsStr" => Some("os_string"),
+            _ => None,
+          }
+        },
+        _ => None,
+      }
+    },
+    _ => None,
+  }
+}
+
+/// The std and third-party types passed as builtins, by their full path.
+const NATIVE_PATHS: &[(&str, &str)] = &[
+  ("std::time::Duration", "Duration"),
+  ("core::time::Duration", "Duration"),
+  ("std::time::SystemTime", "SystemTime"),
+  ("chrono::DateTime", "DateTime"),
+  ("uuid::Uuid", "Uuid"),
+  ("url::Url", "Url"),
+  ("bytes::Bytes", "Bytes"),
+  ("std::path::PathBuf", "PathBuf"),
+  ("std::path::Path", "Path"),
+  ("std::ffi::OsString", "OsString"),
+  ("std::ffi::OsStr", "OsStr"),
+];
+
+/// The name of the native type `path` refers to, if it is a single name such as `Duration`
+/// or the full path of one, such as `std::time::Duration`.
+pub fn type_name(path: &syn::Path) -> Option<&'static str> {
+  let string = path_string(path);
+  NATIVE_PATHS.iter().find_map(|(full, name)| {
+    if string == *full || (path.leading_colon.is_none() && string == *name) {
+      Some(*name)
+    } else {
+      None
+    }
+  })
+}
+
+/// `DateTime<Utc>` is passed as a timestamp, other time zones fall through to `FinchConvert`.
+fn date_time(segment: &syn::PathSegment) -> Option<&'static str> {
+  if let syn::PathArguments::AngleBracketed(generics) = &segment.arguments {
+    if let [syn::GenericArgument::Type(syn::Type::Path(tz))] = generics.args.iter().collect::<Vec<_>>().as_slice() {
+      if tz.qself.is_none() && matches!(path_string(&tz.path).as_str(), "Utc" | "chrono::Utc" | "chrono::offset::Utc") {
+        return Some("date_time");
+      }
+    }
+  }
+
+  None
+}
+
+/// The segments of `path` without their generic arguments or a leading `::`, such as `std::time::Duration`.
+pub fn path_string(path: &syn::Path) -> String {
+  path.segments.iter().map(|segment| segment.ident.to_string()).collect::<Vec<_>>().join("::")
+}
+
+/// Collects the kinds of the native types found anywhere in `ty`.
+pub fn native_types(ty: &syn::Type, kinds: &mut Vec<&'static str>) {
+  if let Some(kind) = native_type(ty) {
+    if !kinds.contains(&kind) {
+      kinds.push(kind);
+    }
+    return;
+  }
+
+  match ty {
+    syn::Type::Path(path) => {
+      for segment in &path.path.segments {
+        if let syn::PathArguments::AngleBracketed(generics) = &segment.arguments {
+          for arg in &generics.args {
+            if let syn::GenericArgument::Type(ty) = arg {
+              native_types(ty, kinds);
+            }
+          }
+        }
+      }
+    },
+    syn::Type::Reference(reference) => native_types(&reference.elem, kinds),
+    syn::Type::Tuple(tuple) => tuple.elems.iter().for_each(|ty| native_types(ty, kinds)),
+    syn::Type::Array(array) => native_types(&array.elem, kinds),
+    syn::Type::Slice(slice) => native_types(&slice.elem, kinds),
+    _ => {},
+  }
+}
+
+/// Exports `___native___{position}___{kind}` for each native type found in an argument
+/// or the return value of a method, where `position` is the argument name or `return`.
+pub fn make_metadata(mangler: &Mangler, member: &[&str], position: &str, ty: &syn::Type) -> proc_macro2::TokenStream {
+  let mut kinds = Vec::new();
+  native_types(ty, &mut kinds);
+
+  let statics = kinds.into_iter().map(|kind| {
+    let mut member = member.to_vec();
+    member.extend(&["native", position, kind]);
+    let (name, export) = mangler.symbol(&member);
+
+    quote!(
+      #export
+      #[allow(non_upper_case_globals)]
+      pub static #name: bool = true;
+    )
+  });
+
+  quote!(#(#statics)*)
+}
+
+/// The cargo feature of finch-gen needed to pass a kind of native type.
+fn feature(kind: &str) -> Option<&'static str> {
+  match kind {
+    "uuid" if !cfg!(feature = "uuid") => Some("uuid"),
+    "date_time" if !cfg!(feature = "chrono") => Some("chrono"),
+    "url" if !cfg!(feature = "url") => Some("url"),
+    _ => None,
+  }
+}
+
+fn missing_feature(ty: &syn::Type, feature: &str) -> proc_macro2::TokenStream {
+  proc_macro2::TokenStream::from(
+    Diagnostic::spanned(syn::spanned::Spanned::span(ty), DiagnosticLevel::Error, &format!("finch-gen[E0026] found '{}' but the '{}' feature is not enabled", quote!(#ty), feature))
+      .note("go to https://finch-gen.github.io/docs/errors/E0026 for more information")
+      .help(format!("enable the '{}' feature for finch-gen in your Cargo.toml", feature))
+      .emit(TokenStream::new()),
+  )
+}
+
+/// The builtin passed in place of `ty`, for the native types not converted as bytes.
+pub fn c_type(ty: &syn::Type) -> Option<syn::Type> {
+  match native_type(ty)? {
+    "duration" => Some(parse_quote!(::finch_gen::builtin::FinchDuration)),
+    "system_time" | "date_time" => Some(parse_quote!(::finch_gen::builtin::FinchTimestamp)),
+    "uuid" => Some(parse_quote!(::finch_gen::builtin::FinchUuid)),
+    "url" => Some(parse_quote!(::finch_gen::builtin::FinchString)),
+    _ => None,
+  }
+}
+
+pub fn convert_arg(ty: &syn::Type, body: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+  let kind = native_type(ty).unwrap();
+  if let Some(feature) = feature(kind) {
+    return missing_feature(ty, feature);
+  }
+
+  match kind {
+    "duration" | "uuid" => quote!(<#ty>::from(#body)),
+    "system_time" => quote!(match (#body).to_system_time() {
+      Ok(x) => x,
+      Err(err) => return ::finch_gen::builtin::FinchResult::Err(err),
+    }),
+    "date_time" => quote!(match (#body).to_date_time() {
+      Ok(x) => x,
+      Err(err) => return ::finch_gen::builtin::FinchResult::Err(err),
+    }),
+    _ => quote!(match ::finch_gen::builtin::url::from_string(#body) {
+      Ok(x) => x,
+      Err(err) => return ::finch_gen::builtin::FinchResult::Err(err),
+    }),
+  }
+}
+
+pub fn convert_ret(ty: &syn::Type, body: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+  let kind = native_type(ty).unwrap();
+  if let Some(feature) = feature(kind) {
+    return missing_feature(ty, feature);
+  }
+
+  match kind {
+    "duration" => quote!(::finch_gen::builtin::FinchDuration::from(#body)),
+    "url" => quote!(::finch_gen::builtin::url::into_string(#body)),
+    "uuid" => quote!(::finch_gen::builtin::FinchUuid::from(#body)),
+    _ => quote!(::finch_gen::builtin::FinchTimestamp::from(#body)),
+  }
+}
@@ -0,0 +1,71 @@
+use syn::parse_quote;
+
+use crate::attr::Strings;
+
+/// Replaces the `String`s passed directly, or in an `Option`, `Result` or tuple, with
+/// `FinchCString`, `FinchCChars` or `FinchString16` for the C and UTF-16 string modes,
+/// and `&str` with `FinchStr16` or `FinchString16` for the UTF-16 mode, which `ToCType` converts.
+///
+/// Some of these conversions can fail, returning early with an error, so strings in
+/// collections and iterators, which are converted in closures, are left as `FinchString`.
+pub fn apply(ty: &syn::Type, strings: Strings, arg: bool) -> syn::Type {
+  match ty {
+    syn::Type::Path(path) if path.path.is_ident("String") => {
+      match (strings, arg) {
+        (Strings::Finch, _) => ty.clone(),
+        (Strings::CChar, true) => parse_quote!(::finch_gen::builtin::FinchCChars),
+        (Strings::Utf16, _) => parse_quote!(::finch_gen::builtin::FinchString16),
+        _ => parse_quote!(::finch_gen::builtin::FinchCString),
+      }
+    },
+
+    syn::Type::Reference(reference) if strings == Strings::Utf16 && reference.mutability.is_none() => {
+      match &*reference.elem {
+        syn::Type::Path(path) if path.path.is_ident("str") && arg => parse_quote!(::finch_gen::builtin::FinchStr16),
+        syn::Type::Path(path) if path.path.is_ident("str") => parse_quote!(::finch_gen::builtin::FinchString16),
+        _ => ty.clone(),
+      }
+    },
+
+    syn::Type::Path(path) if path.qself.is_none() && path.path.segments.len() == 1 => {
+      let mut path = path.clone();
+      let segment = path.path.segments.first_mut().unwrap();
+      if segment.ident == "Option" || segment.ident == "Result" {
+        if let syn::PathArguments::AngleBracketed(generics) = &mut segment.arguments {
+          if let Some(syn::GenericArgument::Type(inner)) = generics.args.first_mut() {
+            *inner = apply(inner, strings, arg);
+          }
+        }
+      }
+
+      syn::Type::Path(path)
+    },
+
+    syn::Type::Tuple(tuple) => {
+      let mut tuple = tuple.clone();
+      for elem in tuple.elems.iter_mut() {
+        *elem = apply(elem, strings, arg);
+      }
+
+      syn::Type::Tuple(tuple)
+    },
+
+    _ => ty.clone(),
+  }
+}
+
+/// Returns `FinchCString`, `FinchCChars`, `FinchString16` or `FinchStr16` if `ty` was
+/// replaced by `apply`.
+pub fn string_type(ty: &syn::Type) -> Option<&syn::Ident> {
+  if let syn::Type::Path(path) = ty {
+    let segments: Vec<_> = path.path.segments.iter().map(|segment| &segment.ident).collect();
+    if path.path.leading_colon.is_some() && segments.len() == 3 && segments[0] == "finch_gen" && segments[1] == "builtin" {
+      match segments[2].to_string().as_str() {
+        "FinchCString" | "FinchCChars" | "FinchString16" | "FinchStr16" => return Some(segments[2]),
+        _ => {},
+      }
+    }
+  }
+
+  None
+}
@@ -0,0 +1,67 @@
+use quote::{quote, quote_spanned};
+
+use crate::attr::{Options, Ownership};
+use crate::naming::Mangler;
+
+/// Generates the `Send`/`Sync` assertions requested with `#[finch_bindgen(send, sync)]`,
+/// and the `___send`, `___sync` and `___thread_safe` metadata of a class.
+///
+/// `___thread_safe` tells the generators whether the host may call the class from any
+/// thread, taking into account whether the ownership backend locks or binds the object.
+pub fn make_thread_safety(mangler: &Mangler, name: &syn::Ident, ownership: Ownership, options: &Options) -> proc_macro2::TokenStream {
+  let mut assertions = Vec::new();
+  if options.send {
+    assertions.push(quote_spanned!(name.span() => assert_send::<#name>();));
+  }
+  if options.sync {
+    assertions.push(quote_spanned!(name.span() => assert_sync::<#name>();));
+  }
+
+  let send = quote!({
+    use ::finch_gen::builtin::thread::NotSend;
+    ::finch_gen::builtin::thread::Check::<#name>::SEND
+  });
+  let sync = quote!({
+    use ::finch_gen::builtin::thread::NotSync;
+    ::finch_gen::builtin::thread::Check::<#name>::SYNC
+  });
+
+  // Locking backends are only safe as nothing may borrow from an object past
+  // its lock, see `finch_gen::builtin::class::ViewOwnership`.
+  let thread_safe = match ownership {
+    Ownership::Locked | Ownership::SharedMutex => quote!(#send),
+    Ownership::ThreadBound => quote!(false),
+    _ => quote!(#send && #sync),
+  };
+
+  let (send_name, send_export) = mangler.symbol(&["send"]);
+  let (sync_name, sync_export) = mangler.symbol(&["sync"]);
+  let (thread_safe_name, thread_safe_export) = mangler.symbol(&["thread_safe"]);
+
+  quote!(
+    const _: () = {
+      fn assert_send<T: Send>() {}
+      fn assert_sync<T: Sync>() {}
+
+      #[allow(dead_code)]
+      fn assert_thread_safety() {
+        #(#assertions)*
+      }
+    };
+
+    /// Whether this class is `Send`, so it can be moved between threads.
+    #send_export
+    #[allow(non_upper_case_globals)]
+    pub static #send_name: bool = #send;
+
+    /// Whether this class is `Sync`, so it can be used from several threads at once.
+    #sync_export
+    #[allow(non_upper_case_globals)]
+    pub static #sync_name: bool = #sync;
+
+    /// Whether the host may call this class from any thread.
+    #thread_safe_export
+    #[allow(non_upper_case_globals)]
+    pub static #thread_safe_name: bool = #thread_safe;
+  )
+}
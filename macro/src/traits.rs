@@ -0,0 +1,150 @@
+use quote::quote;
+
+use crate::{attr::Options, inject_panic_hook, naming::Mangler};
+
+/// Standard traits implemented by an exported struct which are exposed to the host.
+#[derive(Default)]
+pub struct Traits {
+  pub clone: bool,
+  pub debug: bool,
+  pub display: bool,
+  pub eq: bool,
+  pub hash: bool,
+}
+
+impl Traits {
+  /// Collects the traits from `#[derive(...)]` attributes, plus any enabled
+  /// explicitly with `#[finch_bindgen(clone, debug, display, eq, hash)]`.
+  pub fn from_attrs(attrs: &[syn::Attribute], options: &Options) -> Self {
+    let mut traits = Self {
+      clone: options.clone,
+      debug: options.debug,
+      display: options.display,
+      eq: options.eq,
+      hash: options.hash,
+    };
+
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("derive")) {
+      if let Ok(syn::Meta::List(list)) = attr.parse_meta() {
+        for nested in &list.nested {
+          if let syn::NestedMeta::Meta(syn::Meta::Path(path)) = nested {
+            match path.segments.last().unwrap().ident.to_string().as_str() {
+              "Clone" => traits.clone = true,
+              "Debug" => traits.debug = true,
+              "PartialEq" => traits.eq = true,
+              "Hash" => traits.hash = true,
+              _ => {},
+            }
+          }
+        }
+      }
+    }
+
+    traits
+  }
+}
+
+/// Generates the `___clone`, `___debug`, `___display`, `___eq` and `___hash` functions.
+///
+/// Value types are copied by the host, so `___clone` is only generated for classes.
+pub fn make_trait_functions(mangler: &Mangler, kind: &str, traits: &Traits) -> Vec<proc_macro2::TokenStream> {
+  let mut functions = Vec::new();
+  let panic_hook = inject_panic_hook();
+
+  // Classes are passed as handles, while value types are passed by pointer.
+  let (this, other, this_ref, other_ref) = if kind == "class" {
+    (
+      quote!(this: ::finch_gen::builtin::class::Handle<Self>),
+      quote!(other: ::finch_gen::builtin::class::Handle<Self>),
+      quote!(&*::finch_gen::builtin::class::get::<Self>(this)),
+      quote!(&*::finch_gen::builtin::class::get::<Self>(other)),
+    )
+  } else {
+    (quote!(&self), quote!(other: &Self), quote!(self), quote!(other))
+  };
+
+  if traits.clone && kind == "class" {
+    let (clone_name, clone_export) = mangler.symbol(&["clone"]);
+    functions.push(quote!(
+      /// Returns a new copy of this object, which must be dropped separately.
+      #clone_export
+      pub unsafe extern fn #clone_name(#this) -> ::finch_gen::builtin::class::Handle<Self> {
+        #panic_hook
+
+        ::finch_gen::builtin::class::into_handle::<Self>(::std::clone::Clone::clone(#this_ref))
+      }
+    ));
+  }
+
+  if traits.debug {
+    let (debug_name, debug_export) = mangler.symbol(&["debug"]);
+    functions.push(quote!(
+      /// Formats this object using its `Debug` implementation.
+      #debug_export
+      pub unsafe extern fn #debug_name(#this) -> ::finch_gen::builtin::FinchString {
+        #panic_hook
+
+        ::finch_gen::builtin::FinchString::from(format!("{:?}", #this_ref))
+      }
+    ));
+  }
+
+  if traits.display {
+    let (display_name, display_export) = mangler.symbol(&["display"]);
+    functions.push(quote!(
+      /// Formats this object using its `Display` implementation.
+      #display_export
+      pub unsafe extern fn #display_name(#this) -> ::finch_gen::builtin::FinchString {
+        #panic_hook
+
+        ::finch_gen::builtin::FinchString::from(format!("{}", #this_ref))
+      }
+    ));
+  }
+
+  if traits.eq {
+    // Locking the same object twice, or two objects in different orders on two threads,
+    // would deadlock with the lock-backed ownerships.
+    let eq_body = if kind == "class" {
+      quote!(
+        if this == other {
+          let this = #this_ref;
+          ::std::cmp::PartialEq::eq(this, this)
+        } else {
+          let (this, other) = ::finch_gen::builtin::class::get_pair::<Self>(this, other);
+          ::std::cmp::PartialEq::eq(&*this, &*other)
+        }
+      )
+    } else {
+      quote!(::std::cmp::PartialEq::eq(#this_ref, #other_ref))
+    };
+
+    let (eq_name, eq_export) = mangler.symbol(&["eq"]);
+    functions.push(quote!(
+      /// Compares this object with another using its `PartialEq` implementation.
+      #eq_export
+      pub unsafe extern fn #eq_name(#this, #other) -> bool {
+        #panic_hook
+
+        #eq_body
+      }
+    ));
+  }
+
+  if traits.hash {
+    let (hash_name, hash_export) = mangler.symbol(&["hash"]);
+    functions.push(quote!(
+      /// Hashes this object using its `Hash` implementation.
+      #hash_export
+      pub unsafe extern fn #hash_name(#this) -> u64 {
+        #panic_hook
+
+        let mut hasher = ::std::collections::hash_map::DefaultHasher::new();
+        ::std::hash::Hash::hash(#this_ref, &mut hasher);
+        ::std::hash::Hasher::finish(&hasher)
+      }
+    ));
+  }
+
+  functions
+}
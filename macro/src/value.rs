@@ -0,0 +1,172 @@
+use syn::spanned::Spanned;
+use proc_macro::TokenStream;
+use quote::{quote, quote_spanned};
+
+use crate::{abi, attr::{self, Options}, builtin, doc_filter, naming, traits};
+use crate::diagnostic::{Diagnostic, DiagnosticLevel};
+
+/// Replaces every `Self` in `ty` with `name`, so value types are passed by value
+/// rather than boxed like a class.
+pub fn replace_self(ty: &syn::Type, name: &syn::Ident) -> syn::Type {
+  fn replace(tokens: proc_macro2::TokenStream, name: &syn::Ident) -> proc_macro2::TokenStream {
+    tokens.into_iter().map(|token| {
+      match token {
+        proc_macro2::TokenTree::Ident(ident) if ident == "Self" => {
+          proc_macro2::TokenTree::Ident(name.clone())
+        },
+        proc_macro2::TokenTree::Group(group) => {
+          let mut new_group = proc_macro2::Group::new(group.delimiter(), replace(group.stream(), name));
+          new_group.set_span(group.span());
+          proc_macro2::TokenTree::Group(new_group)
+        },
+        token => token,
+      }
+    }).collect()
+  }
+
+  syn::parse2(replace(quote!(#ty), name)).unwrap()
+}
+
+pub fn make_value(item: TokenStream, mut data: syn::ItemStruct, options: &Options) -> TokenStream {
+  let name = data.ident.clone();
+
+  if !data.generics.params.is_empty() {
+    return Diagnostic::spanned(data.generics.span(), DiagnosticLevel::Error, "finch-gen[E0014] value types cannot be generic")
+      .note("go to https://finch-gen.github.io/docs/errors/E0014 for more information")
+      .emit(item);
+  }
+
+  // Hints such as `align(8)` may be given beside `C`, possibly in another #[repr].
+  let reprs: Vec<_> = data.attrs.iter().filter(|attr| attr.path.is_ident("repr")).collect();
+  let is_c = reprs.iter().any(|repr| {
+    match repr.parse_meta() {
+      Ok(syn::Meta::List(list)) => list.nested.iter().any(|nested| {
+        matches!(nested, syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("C") || path.is_ident("transparent"))
+      }),
+      _ => false,
+    }
+  });
+
+  if let Some(repr) = reprs.first() {
+    if !is_c {
+      return Diagnostic::spanned(repr.span(), DiagnosticLevel::Error, "finch-gen[E0015] value types must be #[repr(C)]")
+        .note("go to https://finch-gen.github.io/docs/errors/E0015 for more information")
+        .emit(item);
+    }
+  } else {
+    data.attrs.push(syn::parse_quote!(#[repr(C)]));
+  }
+
+  let mangler = match naming::Mangler::new("value", &name, options) {
+    Ok(mangler) => mangler,
+    Err(diagnostic) => return diagnostic.emit(item),
+  };
+  let mut assertions = Vec::new();
+  let mut offsets = Vec::new();
+
+  for (i, field) in data.fields.iter().enumerate() {
+    let ty = &field.ty;
+    assertions.push(quote_spanned!(ty.span() => assert_value::<#ty>();));
+
+    let member = if let Some(ident) = &field.ident {
+      quote!(#ident)
+    } else {
+      let index = syn::Index::from(i);
+      quote!(#index)
+    };
+
+    let field_options = match attr::Options::from_attrs(&field.attrs) {
+      Ok(options) => options,
+      Err(diagnostic) => return diagnostic.emit(TokenStream::new()),
+    };
+
+    let member_name = match &field.ident {
+      Some(ident) => naming::member_name(ident, &field_options),
+      None => i.to_string(),
+    };
+    let (offset_name, offset_export) = mangler.with_options(&field_options).symbol(&["offset", &member_name]);
+    let doc_comments = field.attrs.iter().filter(doc_filter);
+    offsets.push(quote!(
+      #(#doc_comments)
+      *
+      #offset_export
+      #[allow(non_upper_case_globals)]
+      pub static #offset_name: usize = ::std::mem::offset_of!(#name, #member);
+    ));
+  }
+
+  let value_traits = traits::Traits::from_attrs(&data.attrs, options);
+  let functions = traits::make_trait_functions(&mangler, "value", &value_traits);
+
+  let doc_comments = data.attrs.iter().filter(doc_filter);
+
+  let new_name = mangler.ident(&["type"]);
+  let (size_name, size_export) = mangler.symbol(&["size"]);
+  let (align_name, align_export) = mangler.symbol(&["align"]);
+
+  let naming_key = mangler.key();
+
+  for field in data.fields.iter_mut() {
+    attr::strip_attrs(&mut field.attrs);
+  }
+
+  // The layout is part of the interface of a value type, so the fields are hashed with the functions.
+  let mut layout = data.fields.clone();
+  for field in layout.iter_mut() {
+    field.attrs.clear();
+  }
+
+  let mut items = vec![quote!(#layout)];
+  items.extend(functions.iter().cloned());
+  let interface_hash = abi::make_interface_hash(&mangler, &items);
+
+  if let Err(diagnostic) = mangler.check_collisions(name.span()) {
+    return diagnostic.emit(TokenStream::from(quote!(#data)));
+  }
+  let symbols = mangler.exported();
+
+  let builtins = builtin::assert_builtins();
+  let value_impl = quote!(
+    #data
+
+    #(#doc_comments)
+    *
+    #[allow(non_camel_case_types)]
+    type #new_name = #name;
+
+    unsafe impl ::finch_gen::builtin::FinchValue for #name {
+      const NAMING: &'static str = #naming_key;
+      const SYMBOLS: &'static [&'static str] = &[#(#symbols),*];
+    }
+
+    #[allow(non_snake_case)]
+    impl #new_name {
+      #(#functions)*
+    }
+
+    const _: () = {
+      fn assert_value<T: ::finch_gen::builtin::FinchValue>() {}
+
+      #[allow(dead_code)]
+      fn assert_fields() {
+        #(#assertions)*
+      }
+    };
+
+    #size_export
+    #[allow(non_upper_case_globals)]
+    pub static #size_name: usize = ::std::mem::size_of::<#name>();
+
+    #align_export
+    #[allow(non_upper_case_globals)]
+    pub static #align_name: usize = ::std::mem::align_of::<#name>();
+
+    #(#offsets)*
+
+    #interface_hash
+
+    #builtins
+  );
+
+  TokenStream::from(value_impl)
+}
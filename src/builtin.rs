@@ -1,14 +1,65 @@
 use std::sync::Once;
-use std::ffi::CString;
-use std::os::raw::c_char;
+use std::ffi::{CStr, CString};
+use std::convert::TryFrom;
+use std::mem::ManuallyDrop;
+use std::os::raw::{c_char, c_void};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub static PANIC_HOOK: Once = Once::new();
+
+/// Version of the layout of the builtin types, such as `FinchString` and `FinchResult`,
+/// and of the conventions followed by generated functions.
+///
+/// Exported by `export_builtins!()` as `___builtin___abi_version`, so bindings
+/// can refuse to load a library built against an incompatible version.
+pub const ABI_VERSION: u32 = 1;
 
 #[cfg(feature = "async")]
-use std::cell::RefCell;
+static RUNTIME: std::sync::OnceLock<tokio::runtime::Runtime> = std::sync::OnceLock::new();
 
-pub static PANIC_HOOK: Once = Once::new();
+/// Returns the runtime used to drive exported async functions and streams,
+/// creating it on first use.
+#[cfg(feature = "async")]
+pub fn runtime() -> &'static tokio::runtime::Runtime {
+  RUNTIME.get_or_init(|| tokio::runtime::Runtime::new().expect("failed to create tokio runtime"))
+}
+
+/// Returned when a blocking call is made from a thread already driven by the
+/// runtime, such as from inside a callback passed to a `___next_callback` function.
+#[cfg(feature = "async")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reentrant;
+
+#[cfg(feature = "async")]
+impl std::fmt::Display for Reentrant {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str("cannot block on an async call from a runtime thread, such as from inside a callback")
+  }
+}
+
+#[cfg(feature = "async")]
+impl std::error::Error for Reentrant {}
+
+/// Runs `future` to completion on the shared runtime, or returns [`Reentrant`]
+/// if called from a runtime thread.
+#[cfg(feature = "async")]
+pub fn try_block_on<F: std::future::Future>(future: F) -> Result<F::Output, Reentrant> {
+  if tokio::runtime::Handle::try_current().is_ok() {
+    return Err(Reentrant);
+  }
+
+  Ok(runtime().handle().block_on(future))
+}
 
+/// Runs `future` to completion on the shared runtime.
+///
+/// # Panics
+///
+/// Panics if called from a runtime thread, see [`try_block_on`].
 #[cfg(feature = "async")]
-pub static mut RUNTIME: RefCell<Option<tokio::runtime::Runtime>> = RefCell::new(None);
+pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+  try_block_on(future).unwrap_or_else(|err| panic!("{}", err))
+}
 
 #[repr(C)]
 pub struct FinchString {
@@ -18,6 +69,9 @@ pub struct FinchString {
 }
 
 impl FinchString {
+  /// # Safety
+  ///
+  /// `data` must be valid for reads of `len` bytes of UTF-8.
   pub unsafe fn new(data: *const u8, len: usize) -> Self {
     let ptr = ::std::alloc::alloc(::std::alloc::Layout::from_size_align(len, 1).expect("failed to create ::std::alloc::Layout"));
     ::std::ptr::copy_nonoverlapping(data, ptr, len);
@@ -30,13 +84,27 @@ impl From<String> for FinchString {
   fn from(string: String) -> Self {
     let string = Box::new(string);
     Self {
-      len: string.as_bytes().len(),
+      len: string.len(),
       ptr: string.as_ptr() as *const c_char,
       string: Box::into_raw(string),
     }
   }
 }
 
+impl std::fmt::Display for FinchString {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    unsafe { f.write_str(&*self.string) }
+  }
+}
+
+impl PartialEq for FinchString {
+  fn eq(&self, other: &Self) -> bool {
+    unsafe {
+      ::std::slice::from_raw_parts(self.ptr as *const u8, self.len) == ::std::slice::from_raw_parts(other.ptr as *const u8, other.len)
+    }
+  }
+}
+
 impl Drop for FinchString {
   fn drop(&mut self) {
     drop(unsafe { Box::from_raw(self.string) });
@@ -49,9 +117,51 @@ pub struct FinchCString {
   pub len: usize,
 }
 
-impl From<String> for FinchCString {
-  fn from(string: String) -> Self {
-    let string = CString::new(string).expect("Failed to create CString");
+impl FinchCString {
+  /// Fails if `string` contains a NUL byte, which C would read as its end.
+  pub fn new(string: String) -> Result<Self, FinchString> {
+    CString::new(string).map(Self::from).map_err(|err| FinchString::from(err.to_string()))
+  }
+
+  /// Fails if the string is not valid UTF-8.
+  ///
+  /// # Safety
+  ///
+  /// The string must have been created by `FinchCString::new` or `___builtin___FinchCString___new`.
+  pub unsafe fn into_string(self) -> Result<String, FinchString> {
+    let string = CString::from_raw(ManuallyDrop::new(self).ptr);
+    string.into_string().map_err(|err| FinchString::from(err.utf8_error().to_string()))
+  }
+
+  /// Copies a string borrowed from the host, failing if it is null.
+  ///
+  /// # Safety
+  ///
+  /// `ptr` must be null or point to a NUL-terminated string.
+  pub unsafe fn copy_from_ptr(ptr: *const c_char) -> Result<Self, FinchString> {
+    if ptr.is_null() {
+      return Err(FinchString::from("unexpected null string".to_string()));
+    }
+
+    Ok(Self::from(CString::from(CStr::from_ptr(ptr))))
+  }
+
+  /// Copies a string borrowed from the host, failing if it is null or not valid UTF-8.
+  ///
+  /// # Safety
+  ///
+  /// `ptr` must be null or point to a NUL-terminated string.
+  pub unsafe fn from_ptr(ptr: *const c_char) -> Result<String, FinchString> {
+    if ptr.is_null() {
+      return Err(FinchString::from("unexpected null string".to_string()));
+    }
+
+    CStr::from_ptr(ptr).to_str().map(str::to_string).map_err(|err| FinchString::from(err.to_string()))
+  }
+}
+
+impl From<CString> for FinchCString {
+  fn from(string: CString) -> Self {
     Self {
       len: string.as_bytes().len(),
       ptr: string.into_raw(),
@@ -59,13 +169,452 @@ impl From<String> for FinchCString {
   }
 }
 
+impl TryFrom<String> for FinchCString {
+  type Error = FinchString;
+
+  fn try_from(string: String) -> Result<Self, FinchString> {
+    Self::new(string)
+  }
+}
+
 impl Drop for FinchCString {
   fn drop(&mut self) {
     drop(unsafe { CString::from_raw(self.ptr) });
   }
 }
 
+/// A UTF-16 string, passed in place of `String` with `#[finch_bindgen(string = "utf16")]`.
+///
+/// Strings returned to the host must be freed with `___builtin___FinchString16___drop`.
+/// Strings passed by the host are freed by Rust, so they must be created with
+/// `___builtin___FinchString16___new`.
+#[repr(C)]
+pub struct FinchString16 {
+  pub ptr: *mut u16,
+  pub len: usize,
+  pub cap: usize,
+}
+
+impl FinchString16 {
+  pub fn encode(string: &str) -> Self {
+    let mut units = ManuallyDrop::new(string.encode_utf16().collect::<Vec<_>>());
+    Self {
+      ptr: units.as_mut_ptr(),
+      len: units.len(),
+      cap: units.capacity(),
+    }
+  }
+
+  /// # Safety
+  ///
+  /// `data` must be valid for reads of `len` UTF-16 code units.
+  pub unsafe fn new(data: *const u16, len: usize) -> Self {
+    let mut units = ManuallyDrop::new(FinchStr16 { ptr: data, len }.as_units().to_vec());
+    Self {
+      ptr: units.as_mut_ptr(),
+      len: units.len(),
+      cap: units.capacity(),
+    }
+  }
+
+  /// Fails if the string contains unpaired surrogates.
+  ///
+  /// # Safety
+  ///
+  /// The string must have been created by `FinchString16::new` or `FinchString16::encode`.
+  pub unsafe fn into_string(self) -> Result<String, FinchString> {
+    FinchStr16 { ptr: self.ptr, len: self.len }.to_string()
+  }
+}
+
+impl Drop for FinchString16 {
+  fn drop(&mut self) {
+    drop(unsafe { Vec::from_raw_parts(self.ptr, self.len, self.cap) });
+  }
+}
+
+/// A UTF-16 string borrowed from the host for the duration of a call, passed in
+/// place of `&str` arguments with `#[finch_bindgen(string = "utf16")]`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FinchStr16 {
+  pub ptr: *const u16,
+  pub len: usize,
+}
+
+impl FinchStr16 {
+  unsafe fn as_units<'a>(self) -> &'a [u16] {
+    if self.len == 0 {
+      &[]
+    } else {
+      ::std::slice::from_raw_parts(self.ptr, self.len)
+    }
+  }
+
+  /// Fails if the string contains unpaired surrogates.
+  ///
+  /// # Safety
+  ///
+  /// `ptr` must be valid for reads of `len` UTF-16 code units.
+  pub unsafe fn to_string(self) -> Result<String, FinchString> {
+    String::from_utf16(self.as_units()).map_err(|err| FinchString::from(err.to_string()))
+  }
+}
+
+/// Conversions of `OsString` and `PathBuf` to and from the bytes passed to the host,
+/// which are the raw bytes on Unix, and UTF-8 elsewhere.
+pub mod os {
+  use std::borrow::Cow;
+  use std::ffi::{OsStr, OsString};
+
+  use super::{FinchBytes, FinchSlice};
+
+  pub fn into_bytes(string: OsString) -> FinchBytes {
+    FinchBytes::from(string.into_encoded_bytes())
+  }
+
+  pub fn as_slice(string: &OsStr) -> FinchSlice<u8> {
+    FinchSlice::from(string.as_encoded_bytes())
+  }
+
+  /// # Safety
+  ///
+  /// See `FinchBytes::into_vec`.
+  pub unsafe fn from_bytes(bytes: FinchBytes) -> OsString {
+    let bytes = bytes.into_vec();
+
+    #[cfg(unix)]
+    return std::os::unix::ffi::OsStringExt::from_vec(bytes);
+
+    #[cfg(not(unix))]
+    return OsString::from(String::from_utf8_lossy(&bytes).into_owned());
+  }
+
+  /// # Safety
+  ///
+  /// See `FinchSlice::as_slice`.
+  pub unsafe fn from_slice<'a>(slice: FinchSlice<u8>) -> Cow<'a, OsStr> {
+    let bytes = slice.as_slice();
+
+    #[cfg(unix)]
+    return Cow::Borrowed(std::os::unix::ffi::OsStrExt::from_bytes(bytes));
+
+    #[cfg(not(unix))]
+    return Cow::Owned(OsString::from(String::from_utf8_lossy(bytes).into_owned()));
+  }
+}
+
+/// A `Duration`, as whole seconds and the remaining nanoseconds.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FinchDuration {
+  pub secs: u64,
+  pub nanos: u32,
+}
+
+impl From<Duration> for FinchDuration {
+  fn from(duration: Duration) -> Self {
+    Self {
+      secs: duration.as_secs(),
+      nanos: duration.subsec_nanos(),
+    }
+  }
+}
+
+impl From<FinchDuration> for Duration {
+  fn from(duration: FinchDuration) -> Self {
+    Duration::from_secs(duration.secs).saturating_add(Duration::from_nanos(duration.nanos as u64))
+  }
+}
+
+/// A point in time, as seconds and nanoseconds since the Unix epoch, for `SystemTime`
+/// and `chrono::DateTime<Utc>`.
+///
+/// `secs` is negative for times before the epoch, while `nanos` always counts forwards,
+/// so one nanosecond before the epoch is `{ secs: -1, nanos: 999_999_999 }`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FinchTimestamp {
+  pub secs: i64,
+  pub nanos: u32,
+}
+
+impl From<SystemTime> for FinchTimestamp {
+  fn from(time: SystemTime) -> Self {
+    match time.duration_since(UNIX_EPOCH) {
+      Ok(after) => Self {
+        secs: after.as_secs() as i64,
+        nanos: after.subsec_nanos(),
+      },
+      Err(err) => {
+        let before = err.duration();
+        if before.subsec_nanos() == 0 {
+          Self { secs: -(before.as_secs() as i64), nanos: 0 }
+        } else {
+          Self { secs: -(before.as_secs() as i64) - 1, nanos: 1_000_000_000 - before.subsec_nanos() }
+        }
+      },
+    }
+  }
+}
+
+impl FinchTimestamp {
+  /// Fails if the time cannot be represented by `SystemTime` on this platform.
+  pub fn to_system_time(self) -> Result<SystemTime, FinchString> {
+    let nanos = Duration::from_nanos(self.nanos as u64);
+    let time = if self.secs >= 0 {
+      UNIX_EPOCH.checked_add(Duration::from_secs(self.secs as u64))
+    } else {
+      UNIX_EPOCH.checked_sub(Duration::from_secs(self.secs.unsigned_abs()))
+    };
+
+    time.and_then(|time| time.checked_add(nanos)).ok_or_else(|| self.out_of_range("SystemTime"))
+  }
+
+  /// Fails if the time cannot be represented by `DateTime<Utc>`.
+  #[cfg(feature = "chrono")]
+  pub fn to_date_time(self) -> Result<chrono::DateTime<chrono::Utc>, FinchString> {
+    chrono::DateTime::from_timestamp(self.secs, self.nanos).ok_or_else(|| self.out_of_range("DateTime"))
+  }
+
+  fn out_of_range(self, ty: &str) -> FinchString {
+    FinchString::from(format!("timestamp {}.{:09} out of range for '{}'", self.secs, self.nanos, ty))
+  }
+}
+
+#[cfg(feature = "chrono")]
+impl From<chrono::DateTime<chrono::Utc>> for FinchTimestamp {
+  fn from(time: chrono::DateTime<chrono::Utc>) -> Self {
+    Self {
+      secs: time.timestamp(),
+      nanos: time.timestamp_subsec_nanos(),
+    }
+  }
+}
+
+/// A `uuid::Uuid`, as its 16 bytes in big-endian order.
+#[cfg(feature = "uuid")]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FinchUuid {
+  pub bytes: [u8; 16],
+}
+
+#[cfg(feature = "uuid")]
+impl From<uuid::Uuid> for FinchUuid {
+  fn from(uuid: uuid::Uuid) -> Self {
+    Self {
+      bytes: *uuid.as_bytes(),
+    }
+  }
+}
+
+#[cfg(feature = "uuid")]
+impl From<FinchUuid> for uuid::Uuid {
+  fn from(uuid: FinchUuid) -> Self {
+    uuid::Uuid::from_bytes(uuid.bytes)
+  }
+}
+
+/// Conversions of `url::Url`, passed as a `FinchString`.
+#[cfg(feature = "url")]
+pub mod url {
+  use super::FinchString;
+
+  pub fn into_string(url: ::url::Url) -> FinchString {
+    FinchString::from(String::from(url))
+  }
+
+  /// Fails if the string is not a valid URL.
+  ///
+  /// # Safety
+  ///
+  /// The string must have been created by `___builtin___FinchString___new`.
+  pub unsafe fn from_string(string: FinchString) -> Result<::url::Url, FinchString> {
+    let string = *Box::from_raw(::std::mem::ManuallyDrop::new(string).string);
+    ::url::Url::parse(&string).map_err(|err| FinchString::from(err.to_string()))
+  }
+}
+
+/// A NUL-terminated string borrowed from the host for the duration of a call,
+/// passed in place of `String` arguments with `#[finch_bindgen(string = "c_char")]`.
+pub type FinchCChars = *const c_char;
+
+/// A string borrowed from an object rather than copied, for methods returning `&str`.
+///
+/// It is only valid while the object it was borrowed from is alive and not mutated,
+/// so the host must keep the receiver alive and must not free it.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FinchStr {
+  pub ptr: *const c_char,
+  pub len: usize,
+}
+
+impl FinchStr {
+  /// # Safety
+  ///
+  /// `ptr` must be valid for reads of `len` bytes of UTF-8 for `'a`.
+  pub unsafe fn as_str<'a>(self) -> &'a str {
+    ::std::str::from_utf8_unchecked(::std::slice::from_raw_parts(self.ptr as *const u8, self.len))
+  }
+}
+
+impl From<&str> for FinchStr {
+  fn from(string: &str) -> Self {
+    Self {
+      ptr: string.as_ptr() as *const c_char,
+      len: string.len(),
+    }
+  }
+}
+
+/// A slice borrowed from an object rather than copied, for methods returning `&[T]`.
+///
+/// Like `FinchStr`, it is only valid while the object it was borrowed from is alive
+/// and not mutated.
+#[repr(C)]
+pub struct FinchSlice<T> {
+  pub ptr: *const T,
+  pub len: usize,
+}
+
+impl<T> Clone for FinchSlice<T> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<T> Copy for FinchSlice<T> {}
+
+impl<T: FinchValue> FinchSlice<T> {
+  /// # Safety
+  ///
+  /// `ptr` must be valid for reads of `len` items for `'a`.
+  pub unsafe fn as_slice<'a>(self) -> &'a [T] {
+    if self.len == 0 {
+      &[]
+    } else {
+      ::std::slice::from_raw_parts(self.ptr, self.len)
+    }
+  }
+}
+
+impl<T: FinchValue> From<&[T]> for FinchSlice<T> {
+  fn from(slice: &[T]) -> Self {
+    Self {
+      ptr: slice.as_ptr(),
+      len: slice.len(),
+    }
+  }
+}
+
+/// A slice borrowed mutably, for arguments such as `&mut [u8]` which Rust writes into.
+///
+/// When passed by the host, the buffer stays owned by the host and is only
+/// borrowed for the duration of the call.
+#[repr(C)]
+pub struct FinchSliceMut<T> {
+  pub ptr: *mut T,
+  pub len: usize,
+}
+
+impl<T: FinchValue> FinchSliceMut<T> {
+  /// # Safety
+  ///
+  /// `ptr` must be valid for reads and writes of `len` items for `'a`, and not aliased.
+  pub unsafe fn as_mut_slice<'a>(self) -> &'a mut [T] {
+    if self.len == 0 {
+      &mut []
+    } else {
+      ::std::slice::from_raw_parts_mut(self.ptr, self.len)
+    }
+  }
+}
+
+impl<T: FinchValue> From<&mut [T]> for FinchSliceMut<T> {
+  fn from(slice: &mut [T]) -> Self {
+    Self {
+      ptr: slice.as_mut_ptr(),
+      len: slice.len(),
+    }
+  }
+}
+
+/// A byte buffer moved across the FFI boundary without copying, for `Vec<u8>` and `Bytes`.
+///
+/// Buffers returned to the host must be freed with `___builtin___FinchBytes___drop`.
+/// Buffers passed by the host are freed by Rust, so they must be allocated with
+/// `___builtin___FinchBytes___new`.
+#[repr(C)]
+pub struct FinchBytes {
+  pub ptr: *mut u8,
+  pub len: usize,
+  pub cap: usize,
+}
+
+impl FinchBytes {
+  /// Allocates a zeroed buffer of `len` bytes for the host to fill.
+  pub fn new(len: usize) -> Self {
+    Self::from(vec![0; len])
+  }
+
+  /// # Safety
+  ///
+  /// The buffer must have been created by `FinchBytes::from` or `FinchBytes::new`.
+  pub unsafe fn into_vec(self) -> Vec<u8> {
+    let bytes = ManuallyDrop::new(self);
+    Vec::from_raw_parts(bytes.ptr, bytes.len, bytes.cap)
+  }
+}
+
+impl From<Vec<u8>> for FinchBytes {
+  fn from(vec: Vec<u8>) -> Self {
+    let mut vec = ManuallyDrop::new(vec);
+    Self {
+      ptr: vec.as_mut_ptr(),
+      len: vec.len(),
+      cap: vec.capacity(),
+    }
+  }
+}
+
+impl Drop for FinchBytes {
+  fn drop(&mut self) {
+    drop(unsafe { Vec::from_raw_parts(self.ptr, self.len, self.cap) });
+  }
+}
+
+/// A host function pointer passed in place of a Rust closure.
+///
+/// `callback` is always called with `user_data` as its first argument,
+/// `free` is called with `user_data` once the closure is dropped.
+///
+/// Values passed to `callback`, such as `FinchString` arguments, are owned by the host,
+/// which must free them with the matching builtin, such as `___builtin___FinchString___drop`.
+#[repr(C)]
+pub struct FinchClosure<F> {
+  pub callback: F,
+  pub user_data: *mut c_void,
+  pub free: Option<unsafe extern "C" fn(*mut c_void)>,
+}
+
+impl<F> Drop for FinchClosure<F> {
+  fn drop(&mut self) {
+    if let Some(free) = self.free {
+      unsafe { free(self.user_data) }
+    }
+  }
+}
+
+// The host is responsible for making its callbacks safe to call from any thread,
+// as Rust may call and drop them from whichever thread holds them. This also
+// applies to the vtables of callback traits.
+unsafe impl<F> Send for FinchClosure<F> {}
+unsafe impl<F> Sync for FinchClosure<F> {}
+
 #[repr(C)]
+#[derive(PartialEq)]
 pub enum FinchOption<T> {
   Some(T),
   None,
@@ -75,4 +624,1424 @@ pub enum FinchOption<T> {
 pub enum FinchResult<T> {
   Ok(T),
   Err(FinchString),
+  /// Returned by the `___cancellable` variant of an async method when its
+  /// cancellation token fires or its timeout elapses.
+  Cancelled,
+}
+
+/// Type-erased accessors for a [`FinchMap`], used by the builtin map symbols.
+#[repr(C)]
+pub struct FinchMapVTable {
+  pub key: unsafe extern "C" fn(*const FinchMap<c_void, c_void>, usize) -> *const c_void,
+  pub value: unsafe extern "C" fn(*const FinchMap<c_void, c_void>, usize) -> *const c_void,
+  pub get: unsafe extern "C" fn(*const FinchMap<c_void, c_void>, *const c_void) -> *const c_void,
+  pub drop: unsafe extern "C" fn(*mut FinchMap<c_void, c_void>),
+}
+
+/// A map passed across the FFI boundary as two parallel arrays of `len` entries.
+///
+/// Maps returned from Rust carry a `vtable` and must be freed with the drop symbol.
+/// Maps passed in by the host have a null `vtable`; Rust takes ownership of every
+/// entry but leaves the arrays themselves to the host.
+#[repr(C)]
+pub struct FinchMap<K, V> {
+  pub len: usize,
+  pub keys: *mut K,
+  pub values: *mut V,
+  pub vtable: *const FinchMapVTable,
+}
+
+unsafe extern "C" fn map_key<K, V>(map: *const FinchMap<c_void, c_void>, index: usize) -> *const c_void {
+  let map = &*(map as *const FinchMap<K, V>);
+  if index < map.len {
+    map.keys.add(index) as *const c_void
+  } else {
+    std::ptr::null()
+  }
+}
+
+unsafe extern "C" fn map_value<K, V>(map: *const FinchMap<c_void, c_void>, index: usize) -> *const c_void {
+  let map = &*(map as *const FinchMap<K, V>);
+  if index < map.len {
+    map.values.add(index) as *const c_void
+  } else {
+    std::ptr::null()
+  }
+}
+
+unsafe extern "C" fn map_get<K: PartialEq, V>(map: *const FinchMap<c_void, c_void>, key: *const c_void) -> *const c_void {
+  let map = &*(map as *const FinchMap<K, V>);
+  let key = &*(key as *const K);
+  for i in 0..map.len {
+    if *map.keys.add(i) == *key {
+      return map.values.add(i) as *const c_void;
+    }
+  }
+
+  std::ptr::null()
+}
+
+unsafe extern "C" fn map_drop<K, V>(map: *mut FinchMap<c_void, c_void>) {
+  let map = &*(map as *const FinchMap<K, V>);
+  drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(map.keys, map.len)));
+  drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(map.values, map.len)));
+}
+
+impl<K: PartialEq, V> FinchMap<K, V> {
+  const VTABLE: FinchMapVTable = FinchMapVTable {
+    key: map_key::<K, V>,
+    value: map_value::<K, V>,
+    get: map_get::<K, V>,
+    drop: map_drop::<K, V>,
+  };
+
+  pub fn from_entries<I: IntoIterator<Item = (K, V)>>(entries: I) -> Self {
+    let (keys, values): (Vec<K>, Vec<V>) = entries.into_iter().unzip();
+    let keys = Box::into_raw(keys.into_boxed_slice());
+    let values = Box::into_raw(values.into_boxed_slice());
+    Self {
+      len: keys.len(),
+      keys: keys as *mut K,
+      values: values as *mut V,
+      vtable: &Self::VTABLE,
+    }
+  }
+}
+
+impl<K, V> FinchMap<K, V> {
+  /// Takes ownership of every entry, freeing the arrays if they were allocated by Rust.
+  ///
+  /// # Safety
+  ///
+  /// `keys` and `values` must each point to `len` initialized entries.
+  pub unsafe fn into_entries(self) -> std::vec::IntoIter<(K, V)> {
+    let map = std::mem::ManuallyDrop::new(self);
+    let entries: Vec<(K, V)> = (0..map.len)
+      .map(|i| (std::ptr::read(map.keys.add(i)), std::ptr::read(map.values.add(i))))
+      .collect();
+
+    if !map.vtable.is_null() {
+      drop(Vec::from_raw_parts(map.keys, 0, map.len));
+      drop(Vec::from_raw_parts(map.values, 0, map.len));
+    }
+
+    entries.into_iter()
+  }
+}
+
+impl<K, V> Drop for FinchMap<K, V> {
+  fn drop(&mut self) {
+    if !self.vtable.is_null() {
+      unsafe { ((*self.vtable).drop)(self as *mut Self as *mut FinchMap<c_void, c_void>) }
+    }
+  }
+}
+
+/// Type-erased accessors for a [`FinchSet`], used by the builtin set symbols.
+#[repr(C)]
+pub struct FinchSetVTable {
+  pub item: unsafe extern "C" fn(*const FinchSet<c_void>, usize) -> *const c_void,
+  pub contains: unsafe extern "C" fn(*const FinchSet<c_void>, *const c_void) -> bool,
+  pub drop: unsafe extern "C" fn(*mut FinchSet<c_void>),
+}
+
+/// A set passed across the FFI boundary as an array of `len` items.
+///
+/// Ownership follows the same rules as [`FinchMap`].
+#[repr(C)]
+pub struct FinchSet<T> {
+  pub len: usize,
+  pub items: *mut T,
+  pub vtable: *const FinchSetVTable,
+}
+
+unsafe extern "C" fn set_item<T>(set: *const FinchSet<c_void>, index: usize) -> *const c_void {
+  let set = &*(set as *const FinchSet<T>);
+  if index < set.len {
+    set.items.add(index) as *const c_void
+  } else {
+    std::ptr::null()
+  }
+}
+
+unsafe extern "C" fn set_contains<T: PartialEq>(set: *const FinchSet<c_void>, item: *const c_void) -> bool {
+  let set = &*(set as *const FinchSet<T>);
+  let item = &*(item as *const T);
+  (0..set.len).any(|i| *set.items.add(i) == *item)
+}
+
+unsafe extern "C" fn set_drop<T>(set: *mut FinchSet<c_void>) {
+  let set = &*(set as *const FinchSet<T>);
+  drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(set.items, set.len)));
+}
+
+impl<T: PartialEq> FinchSet<T> {
+  const VTABLE: FinchSetVTable = FinchSetVTable {
+    item: set_item::<T>,
+    contains: set_contains::<T>,
+    drop: set_drop::<T>,
+  };
+
+  pub fn from_items<I: IntoIterator<Item = T>>(items: I) -> Self {
+    let items = Box::into_raw(items.into_iter().collect::<Vec<T>>().into_boxed_slice());
+    Self {
+      len: items.len(),
+      items: items as *mut T,
+      vtable: &Self::VTABLE,
+    }
+  }
+}
+
+impl<T> FinchSet<T> {
+  /// Takes ownership of every item, freeing the array if it was allocated by Rust.
+  ///
+  /// # Safety
+  ///
+  /// `items` must point to `len` initialized items.
+  pub unsafe fn into_items(self) -> std::vec::IntoIter<T> {
+    let set = std::mem::ManuallyDrop::new(self);
+    let items: Vec<T> = (0..set.len).map(|i| std::ptr::read(set.items.add(i))).collect();
+
+    if !set.vtable.is_null() {
+      drop(Vec::from_raw_parts(set.items, 0, set.len));
+    }
+
+    items.into_iter()
+  }
+}
+
+impl<T> Drop for FinchSet<T> {
+  fn drop(&mut self) {
+    if !self.vtable.is_null() {
+      unsafe { ((*self.vtable).drop)(self as *mut Self as *mut FinchSet<c_void>) }
+    }
+  }
+}
+
+macro_rules! finch_tuple {
+  ($name:ident, $($field:ident: $ty:ident),+) => {
+    /// A tuple passed across the FFI boundary, with fields `_0` to `_n`.
+    #[repr(C)]
+    #[derive(PartialEq)]
+    pub struct $name<$($ty),+> {
+      $(pub $field: $ty),+
+    }
+  };
+}
+
+finch_tuple!(FinchTuple1, _0: A);
+finch_tuple!(FinchTuple2, _0: A, _1: B);
+finch_tuple!(FinchTuple3, _0: A, _1: B, _2: C);
+finch_tuple!(FinchTuple4, _0: A, _1: B, _2: C, _3: D);
+finch_tuple!(FinchTuple5, _0: A, _1: B, _2: C, _3: D, _4: E);
+finch_tuple!(FinchTuple6, _0: A, _1: B, _2: C, _3: D, _4: E, _5: F);
+finch_tuple!(FinchTuple7, _0: A, _1: B, _2: C, _3: D, _4: E, _5: F, _6: G);
+finch_tuple!(FinchTuple8, _0: A, _1: B, _2: C, _3: D, _4: E, _5: F, _6: G, _7: H);
+finch_tuple!(FinchTuple9, _0: A, _1: B, _2: C, _3: D, _4: E, _5: F, _6: G, _7: H, _8: I);
+finch_tuple!(FinchTuple10, _0: A, _1: B, _2: C, _3: D, _4: E, _5: F, _6: G, _7: H, _8: I, _9: J);
+finch_tuple!(FinchTuple11, _0: A, _1: B, _2: C, _3: D, _4: E, _5: F, _6: G, _7: H, _8: I, _9: J, _10: K);
+finch_tuple!(FinchTuple12, _0: A, _1: B, _2: C, _3: D, _4: E, _5: F, _6: G, _7: H, _8: I, _9: J, _10: K, _11: L);
+
+/// A fixed-size array passed across the FFI boundary by value.
+#[repr(C)]
+#[derive(PartialEq)]
+pub struct FinchArray<T, const N: usize> {
+  pub data: [T; N],
+}
+
+/// Marker for structs exported with `#[finch_bindgen]` as opaque classes,
+/// checked by every exported impl block.
+///
+/// # Safety
+///
+/// The type must have been exported with `#[finch_bindgen]`, so its drop function exists.
+#[diagnostic::on_unimplemented(
+  message = "finch-gen[E0018] '{Self}' is not a public struct exported with #[finch_bindgen]",
+  label = "impl exported for a struct which is not",
+  note = "add #[finch_bindgen] to a public struct, or #[finch_bindgen(value)] to both the struct and this impl for value types",
+  note = "go to https://finch-gen.github.io/docs/errors/E0018 for more information",
+)]
+pub unsafe trait FinchClass: Sized {
+  /// Identifies the namespace, name, prefix and naming scheme of the exported symbols.
+  const NAMING: &'static str = "";
+
+  /// The symbols exported for the struct, such as its getters and setters, which the
+  /// methods of its impl blocks must not reuse.
+  ///
+  /// With the snake scheme, the getter of `size` below is exported as `rust_out_config_get_size`:
+  ///
+  /// ```
+  /// # use finch_gen::finch_bindgen;
+  /// # finch_gen::export_builtins!();
+  /// #[finch_bindgen(naming = "snake")]
+  /// pub struct Config {
+  ///   pub size: u32,
+  /// }
+  ///
+  /// #[finch_bindgen(naming = "snake")]
+  /// impl Config {
+  ///   pub fn size(&self) -> u32 {
+  ///     self.size
+  ///   }
+  /// }
+  /// # fn main() {}
+  /// ```
+  ///
+  /// so a method named `get_size` is rejected with `finch-gen[E0020]`:
+  ///
+  /// ```compile_fail
+  /// # use finch_gen::finch_bindgen;
+  /// # finch_gen::export_builtins!();
+  /// #[finch_bindgen(naming = "snake")]
+  /// pub struct Config {
+  ///   pub size: u32,
+  /// }
+  ///
+  /// #[finch_bindgen(naming = "snake")]
+  /// impl Config {
+  ///   pub fn get_size(&self) -> u32 {
+  ///     self.size
+  ///   }
+  /// }
+  /// # fn main() {}
+  /// ```
+  const SYMBOLS: &'static [&'static str] = &[];
+
+  /// How objects of this class are owned, and the handles passed to the host.
+  type Ownership: class::Ownership<Self>;
+}
+
+/// Conversions between exported classes and the handles passed to the host,
+/// used by every generated function taking or returning a class.
+pub mod class {
+  use std::any::TypeId;
+  use std::collections::BTreeMap;
+  use std::ops::{Deref, DerefMut};
+  use std::mem::ManuallyDrop;
+  use std::sync::{Arc, Mutex, MutexGuard};
+  use std::thread::ThreadId;
+
+  use super::FinchClass;
+
+  /// An ownership backend for exported classes.
+  ///
+  /// # Safety
+  ///
+  /// `get`, `get_mut` and `take` must only return objects created by `into_handle`.
+  pub unsafe trait Ownership<T> {
+    /// The handle passed to the host in place of the object, ordered so objects
+    /// can be locked in a consistent order.
+    type Handle: Copy + Ord;
+
+    /// A reference to the object, which may hold a lock.
+    type Ref<'a>: Deref<Target = T> where T: 'a;
+
+    /// A mutable reference to the object, which may hold a lock.
+    type RefMut<'a>: DerefMut<Target = T> where T: 'a;
+
+    /// Moves `value` into a new handle, which the host must drop.
+    fn into_handle(value: T) -> Self::Handle;
+
+    /// # Safety
+    ///
+    /// `handle` must be valid, unless the backend checks it.
+    unsafe fn get<'a>(handle: Self::Handle) -> Self::Ref<'a>;
+
+    /// # Safety
+    ///
+    /// `handle` must be valid, unless the backend checks it.
+    unsafe fn get_mut<'a>(handle: Self::Handle) -> Self::RefMut<'a>;
+
+    /// Moves the object out of `handle`, invalidating it.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be valid, unless the backend checks it.
+    unsafe fn take(handle: Self::Handle) -> T;
+
+    /// Drops `handle`, and the object unless other handles refer to it.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be valid, unless the backend checks it.
+    unsafe fn drop_handle(handle: Self::Handle) {
+      drop(Self::take(handle))
+    }
+  }
+
+  /// The handle type of the class `T`.
+  pub type Handle<T> = <<T as FinchClass>::Ownership as Ownership<T>>::Handle;
+
+  /// Objects are boxed, and the host receives the raw pointer. Nothing is checked,
+  /// so passing a null or dangling pointer is undefined behavior.
+  pub struct Boxed;
+
+  unsafe impl<T> Ownership<T> for Boxed {
+    type Handle = *mut T;
+    type Ref<'a> = &'a T where T: 'a;
+    type RefMut<'a> = &'a mut T where T: 'a;
+
+    fn into_handle(value: T) -> *mut T {
+      Box::into_raw(Box::new(value))
+    }
+
+    unsafe fn get<'a>(handle: *mut T) -> &'a T {
+      &*handle
+    }
+
+    unsafe fn get_mut<'a>(handle: *mut T) -> &'a mut T {
+      &mut *handle
+    }
+
+    unsafe fn take(handle: *mut T) -> T {
+      *Box::from_raw(handle)
+    }
+  }
+
+  unsafe impl<T> BorrowOwnership<T> for Boxed {
+    fn borrow(value: &T) -> *mut T {
+      value as *const T as *mut T
+    }
+  }
+
+  /// Pointers to every object created by `Checked`, with the type of the object.
+  static LIVE: Mutex<BTreeMap<usize, TypeId>> = Mutex::new(BTreeMap::new());
+
+  /// Like `Boxed`, but every pointer received from the host is checked against a
+  /// table of live objects before it is used, so null, dangling, already consumed
+  /// and mistyped pointers abort with a message rather than causing undefined behavior.
+  ///
+  /// Objects are tracked by address, so a dangling pointer is not detected once the
+  /// allocator reuses its address for a new object of the same type, and then refers to
+  /// that object. Use `Handles`, whose handles carry a generation, to detect every stale handle.
+  ///
+  /// Selected with `#[finch_bindgen(checked)]`, or for a whole crate by setting
+  /// `FINCH_GEN_OWNERSHIP=checked` when building it.
+  pub struct Checked;
+
+  impl Checked {
+    fn check<T: 'static>(handle: *mut T, remove: bool) {
+      let mut live = LIVE.lock().unwrap_or_else(|err| err.into_inner());
+      let type_name = std::any::type_name::<T>();
+
+      if handle.is_null() {
+        panic!("finch-gen: null handle passed for '{}'", type_name);
+      }
+
+      match live.get(&(handle as usize)) {
+        Some(id) if *id == TypeId::of::<T>() => {},
+        Some(_) => panic!("finch-gen: handle of another type passed for '{}'", type_name),
+        None => panic!("finch-gen: dangling handle passed for '{}', it was already dropped or consumed", type_name),
+      }
+
+      if remove {
+        live.remove(&(handle as usize));
+      }
+    }
+  }
+
+  unsafe impl<T: 'static> Ownership<T> for Checked {
+    type Handle = *mut T;
+    type Ref<'a> = &'a T where T: 'a;
+    type RefMut<'a> = &'a mut T where T: 'a;
+
+    fn into_handle(value: T) -> *mut T {
+      let handle = Box::into_raw(Box::new(value));
+      LIVE.lock().unwrap_or_else(|err| err.into_inner()).insert(handle as usize, TypeId::of::<T>());
+      handle
+    }
+
+    unsafe fn get<'a>(handle: *mut T) -> &'a T {
+      Self::check(handle, false);
+      &*handle
+    }
+
+    unsafe fn get_mut<'a>(handle: *mut T) -> &'a mut T {
+      Self::check(handle, false);
+      &mut *handle
+    }
+
+    unsafe fn take(handle: *mut T) -> T {
+      Self::check(handle, true);
+      *Box::from_raw(handle)
+    }
+  }
+
+  struct Slot {
+    generation: u32,
+    object: Option<(usize, TypeId)>,
+  }
+
+  struct SlotMap {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+  }
+
+  /// Objects created by `Handles`, indexed by the low 32 bits of their handle.
+  static SLOTS: Mutex<SlotMap> = Mutex::new(SlotMap { slots: Vec::new(), free: Vec::new() });
+
+  /// Objects live in a generational slot map, and the host receives an opaque
+  /// 64-bit handle holding the slot index and generation rather than an address.
+  ///
+  /// Handles are checked like `Checked`, and as a slot's generation changes each time
+  /// it is freed, stale handles are detected even after the slot is reused.
+  /// A handle is never 0, so hosts may use it as null.
+  ///
+  /// Selected with `#[finch_bindgen(handle)]`, or for a whole crate by setting
+  /// `FINCH_GEN_OWNERSHIP=handle` when building it.
+  pub struct Handles;
+
+  impl Handles {
+    fn lookup<T: 'static>(handle: u64, remove: bool) -> *mut T {
+      let mut map = SLOTS.lock().unwrap_or_else(|err| err.into_inner());
+      let SlotMap { slots, free } = &mut *map;
+      let type_name = std::any::type_name::<T>();
+
+      let index = handle as u32;
+      let generation = (handle >> 32) as u32;
+
+      if handle == 0 {
+        panic!("finch-gen: null handle passed for '{}'", type_name);
+      }
+
+      let slot = match slots.get_mut(index as usize) {
+        Some(slot) if slot.generation == generation && slot.object.is_some() => slot,
+        _ => panic!("finch-gen: dangling handle passed for '{}', it was already dropped or consumed", type_name),
+      };
+
+      let (ptr, id) = slot.object.unwrap();
+      if id != TypeId::of::<T>() {
+        panic!("finch-gen: handle of another type passed for '{}'", type_name);
+      }
+
+      if remove {
+        slot.object = None;
+        slot.generation = slot.generation.wrapping_add(1).max(1);
+        free.push(index);
+      }
+
+      ptr as *mut T
+    }
+  }
+
+  unsafe impl<T: 'static> Ownership<T> for Handles {
+    type Handle = u64;
+    type Ref<'a> = &'a T where T: 'a;
+    type RefMut<'a> = &'a mut T where T: 'a;
+
+    fn into_handle(value: T) -> u64 {
+      let object = Some((Box::into_raw(Box::new(value)) as usize, TypeId::of::<T>()));
+
+      let mut map = SLOTS.lock().unwrap_or_else(|err| err.into_inner());
+      let index = if let Some(index) = map.free.pop() {
+        map.slots[index as usize].object = object;
+        index
+      } else {
+        map.slots.push(Slot { generation: 1, object });
+        (map.slots.len() - 1) as u32
+      };
+
+      ((map.slots[index as usize].generation as u64) << 32) | index as u64
+    }
+
+    unsafe fn get<'a>(handle: u64) -> &'a T {
+      &*Self::lookup::<T>(handle, false)
+    }
+
+    unsafe fn get_mut<'a>(handle: u64) -> &'a mut T {
+      &mut *Self::lookup::<T>(handle, false)
+    }
+
+    unsafe fn take(handle: u64) -> T {
+      *Box::from_raw(Self::lookup::<T>(handle, true))
+    }
+  }
+
+  /// Like `Boxed`, but objects are stored as `Mutex<T>` and every method call locks
+  /// the object, so a class which is `Send` but not `Sync` can be called from any thread.
+  ///
+  /// Selected with `#[finch_bindgen(mutex)]`.
+  pub struct Locked;
+
+  unsafe impl<T> Ownership<T> for Locked {
+    type Handle = *mut Mutex<T>;
+    type Ref<'a> = MutexGuard<'a, T> where T: 'a;
+    type RefMut<'a> = MutexGuard<'a, T> where T: 'a;
+
+    fn into_handle(value: T) -> *mut Mutex<T> {
+      Box::into_raw(Box::new(Mutex::new(value)))
+    }
+
+    unsafe fn get<'a>(handle: *mut Mutex<T>) -> MutexGuard<'a, T> {
+      (*handle).lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    unsafe fn get_mut<'a>(handle: *mut Mutex<T>) -> MutexGuard<'a, T> {
+      Self::get(handle)
+    }
+
+    unsafe fn take(handle: *mut Mutex<T>) -> T {
+      Box::from_raw(handle).into_inner().unwrap_or_else(|err| err.into_inner())
+    }
+  }
+
+  /// An object of a `ThreadBound` class dropped from a thread other than the one which created it.
+  struct PendingDrop {
+    thread: ThreadId,
+    handle: usize,
+    drop: unsafe fn(usize),
+  }
+
+  static PENDING: Mutex<Vec<PendingDrop>> = Mutex::new(Vec::new());
+
+  /// Like `Boxed`, but objects remember the thread which created them, and using
+  /// them from another thread panics rather than causing a data race.
+  ///
+  /// Dropping an object from another thread, as the finalizers of garbage collected
+  /// hosts do, queues it to be dropped the next time the creating thread uses any
+  /// `ThreadBound` object. Objects of threads which have exited are leaked.
+  ///
+  /// Selected with `#[finch_bindgen(thread_bound)]`.
+  pub struct ThreadBound;
+
+  impl ThreadBound {
+    fn check<T>(thread: ThreadId) {
+      Self::collect();
+      if thread != std::thread::current().id() {
+        panic!("finch-gen: '{}' used from a thread other than the one which created it", std::any::type_name::<T>());
+      }
+    }
+
+    /// Drops the objects queued for the current thread.
+    fn collect() {
+      let current = std::thread::current().id();
+      let mut pending = PENDING.lock().unwrap_or_else(|err| err.into_inner());
+      if pending.is_empty() {
+        return;
+      }
+
+      // Dropped once the lock is released, as they may drop other objects.
+      let (owned, others) = pending.drain(..).partition::<Vec<_>, _>(|object| object.thread == current);
+      *pending = others;
+      drop(pending);
+
+      for object in owned {
+        unsafe { (object.drop)(object.handle) }
+      }
+    }
+
+    unsafe fn drop_object<T>(handle: usize) {
+      drop(Box::from_raw(handle as *mut (ThreadId, T)))
+    }
+  }
+
+  unsafe impl<T> Ownership<T> for ThreadBound {
+    type Handle = *mut (ThreadId, T);
+    type Ref<'a> = &'a T where T: 'a;
+    type RefMut<'a> = &'a mut T where T: 'a;
+
+    fn into_handle(value: T) -> *mut (ThreadId, T) {
+      Self::collect();
+      Box::into_raw(Box::new((std::thread::current().id(), value)))
+    }
+
+    unsafe fn get<'a>(handle: *mut (ThreadId, T)) -> &'a T {
+      Self::check::<T>((*handle).0);
+      &(*handle).1
+    }
+
+    unsafe fn get_mut<'a>(handle: *mut (ThreadId, T)) -> &'a mut T {
+      Self::check::<T>((*handle).0);
+      &mut (*handle).1
+    }
+
+    unsafe fn take(handle: *mut (ThreadId, T)) -> T {
+      Self::check::<T>((*handle).0);
+      Box::from_raw(handle).1
+    }
+
+    unsafe fn drop_handle(handle: *mut (ThreadId, T)) {
+      let thread = (*handle).0;
+      if thread == std::thread::current().id() {
+        Self::collect();
+        Self::drop_object::<T>(handle as usize);
+      } else {
+        PENDING.lock().unwrap_or_else(|err| err.into_inner()).push(PendingDrop {
+          thread,
+          handle: handle as usize,
+          drop: Self::drop_object::<T>,
+        });
+      }
+    }
+  }
+
+  /// An ownership backend where several handles can refer to the same object.
+  ///
+  /// # Safety
+  ///
+  /// `retain` must return a handle which stays valid until it is dropped,
+  /// independently of `handle`.
+  pub unsafe trait SharedOwnership<T>: Ownership<T> {
+    /// Returns a new handle to the same object, which must be dropped separately.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be valid.
+    unsafe fn retain(handle: Self::Handle) -> Self::Handle;
+  }
+
+  /// An ownership backend whose handles can point to an object owned by another,
+  /// for methods returning `&Other`.
+  ///
+  /// # Safety
+  ///
+  /// `borrow` must return a handle which stays valid for `get` while `value` is alive,
+  /// and which the host never drops.
+  #[diagnostic::on_unimplemented(
+    message = "finch-gen[E0023] '{T}' cannot be returned by reference with the '{Self}' ownership",
+    label = "borrowed handles are only supported for boxed classes",
+    note = "return a clone or an `Arc<Self>` instead, or export '{T}' without an ownership option",
+    note = "go to https://finch-gen.github.io/docs/errors/E0023 for more information",
+  )]
+  pub unsafe trait BorrowOwnership<T>: Ownership<T> {
+    /// Returns a handle to `value` which the host must not drop.
+    fn borrow(value: &T) -> Self::Handle;
+  }
+
+  /// An ownership backend whose references do not hold a lock, so methods may return
+  /// views such as `&str` or `&[T]`, iterators or streams borrowing from the object.
+  ///
+  /// # Safety
+  ///
+  /// `Ownership::Ref` and `Ownership::RefMut` must not guard the object, as views
+  /// outlive them.
+  #[diagnostic::on_unimplemented(
+    message = "finch-gen[E0028] '{T}' cannot return views, iterators or streams borrowing from it with the '{Self}' ownership",
+    label = "the view would outlive the lock on the object",
+    note = "return an owned value such as `String` or `Vec<T>`, or collect the iterator, instead",
+    note = "go to https://finch-gen.github.io/docs/errors/E0028 for more information",
+  )]
+  pub unsafe trait ViewOwnership<T>: Ownership<T> {}
+
+  unsafe impl<T> ViewOwnership<T> for Boxed {}
+  unsafe impl<T: 'static> ViewOwnership<T> for Checked {}
+  unsafe impl<T: 'static> ViewOwnership<T> for Handles {}
+  unsafe impl<T> ViewOwnership<T> for ThreadBound {}
+  unsafe impl<T> ViewOwnership<T> for Shared {}
+
+  /// Objects are stored as `Arc<T>`, and the host receives the pointer from
+  /// `Arc::into_raw`. Each handle returned by `___retain` holds a strong reference,
+  /// released by `___release` or `___drop`.
+  ///
+  /// Methods taking `&mut self` or `self` panic if other handles to the object
+  /// are alive, use `SharedMutex` for objects mutated while shared.
+  ///
+  /// Selected with `#[finch_bindgen(shared)]`.
+  pub struct Shared;
+
+  unsafe impl<T> Ownership<T> for Shared {
+    type Handle = *const T;
+    type Ref<'a> = &'a T where T: 'a;
+    type RefMut<'a> = &'a mut T where T: 'a;
+
+    fn into_handle(value: T) -> *const T {
+      Arc::into_raw(Arc::new(value))
+    }
+
+    unsafe fn get<'a>(handle: *const T) -> &'a T {
+      &*handle
+    }
+
+    unsafe fn get_mut<'a>(handle: *const T) -> &'a mut T {
+      let mut arc = ManuallyDrop::new(Arc::from_raw(handle));
+      if Arc::get_mut(&mut arc).is_none() {
+        panic!("finch-gen: '&mut self' method called on a shared '{}' with other handles alive", std::any::type_name::<T>());
+      }
+
+      &mut *(handle as *mut T)
+    }
+
+    unsafe fn take(handle: *const T) -> T {
+      match Arc::try_unwrap(Arc::from_raw(handle)) {
+        Ok(value) => value,
+        Err(arc) => {
+          std::mem::forget(arc);
+          panic!("finch-gen: 'self' method called on a shared '{}' with other handles alive", std::any::type_name::<T>());
+        },
+      }
+    }
+
+    unsafe fn drop_handle(handle: *const T) {
+      drop(Arc::from_raw(handle))
+    }
+  }
+
+  unsafe impl<T> SharedOwnership<T> for Shared {
+    unsafe fn retain(handle: *const T) -> *const T {
+      Arc::increment_strong_count(handle);
+      handle
+    }
+  }
+
+  /// Like `Shared`, but objects are stored as `Arc<Mutex<T>>` and every method call
+  /// locks the object, so methods taking `&mut self` work while it is shared.
+  ///
+  /// Selected with `#[finch_bindgen(shared_mutex)]`.
+  pub struct SharedMutex;
+
+  unsafe impl<T> Ownership<T> for SharedMutex {
+    type Handle = *const Mutex<T>;
+    type Ref<'a> = MutexGuard<'a, T> where T: 'a;
+    type RefMut<'a> = MutexGuard<'a, T> where T: 'a;
+
+    fn into_handle(value: T) -> *const Mutex<T> {
+      Arc::into_raw(Arc::new(Mutex::new(value)))
+    }
+
+    unsafe fn get<'a>(handle: *const Mutex<T>) -> MutexGuard<'a, T> {
+      (*handle).lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    unsafe fn get_mut<'a>(handle: *const Mutex<T>) -> MutexGuard<'a, T> {
+      Self::get(handle)
+    }
+
+    unsafe fn take(handle: *const Mutex<T>) -> T {
+      match Arc::try_unwrap(Arc::from_raw(handle)) {
+        Ok(mutex) => mutex.into_inner().unwrap_or_else(|err| err.into_inner()),
+        Err(arc) => {
+          std::mem::forget(arc);
+          panic!("finch-gen: 'self' method called on a shared '{}' with other handles alive", std::any::type_name::<T>());
+        },
+      }
+    }
+
+    unsafe fn drop_handle(handle: *const Mutex<T>) {
+      drop(Arc::from_raw(handle))
+    }
+  }
+
+  unsafe impl<T> SharedOwnership<T> for SharedMutex {
+    unsafe fn retain(handle: *const Mutex<T>) -> *const Mutex<T> {
+      Arc::increment_strong_count(handle);
+      handle
+    }
+  }
+
+  #[inline(always)]
+  pub fn into_handle<T: FinchClass>(value: T) -> Handle<T> {
+    T::Ownership::into_handle(value)
+  }
+
+  /// # Safety
+  ///
+  /// See `Ownership::get`.
+  #[inline(always)]
+  pub unsafe fn get<'a, T: FinchClass>(handle: Handle<T>) -> <T::Ownership as Ownership<T>>::Ref<'a> {
+    T::Ownership::get(handle)
+  }
+
+  /// Returns references to two distinct objects, taken in the order of their handles so
+  /// two threads locking the same pair of objects cannot deadlock.
+  ///
+  /// # Safety
+  ///
+  /// See `Ownership::get`. `a` and `b` must not be the same handle.
+  pub unsafe fn get_pair<'a, T: FinchClass>(a: Handle<T>, b: Handle<T>) -> (<T::Ownership as Ownership<T>>::Ref<'a>, <T::Ownership as Ownership<T>>::Ref<'a>) {
+    if a < b {
+      let a = T::Ownership::get(a);
+      (a, T::Ownership::get(b))
+    } else {
+      let b = T::Ownership::get(b);
+      (T::Ownership::get(a), b)
+    }
+  }
+
+  /// # Safety
+  ///
+  /// See `Ownership::get_mut`.
+  #[inline(always)]
+  pub unsafe fn get_mut<'a, T: FinchClass>(handle: Handle<T>) -> <T::Ownership as Ownership<T>>::RefMut<'a> {
+    T::Ownership::get_mut(handle)
+  }
+
+  /// # Safety
+  ///
+  /// See `Ownership::take`.
+  #[inline(always)]
+  pub unsafe fn take<T: FinchClass>(handle: Handle<T>) -> T {
+    T::Ownership::take(handle)
+  }
+
+  /// # Safety
+  ///
+  /// See `Ownership::drop_handle`.
+  #[inline(always)]
+  pub unsafe fn drop_handle<T: FinchClass>(handle: Handle<T>) {
+    T::Ownership::drop_handle(handle)
+  }
+
+  /// # Safety
+  ///
+  /// See `SharedOwnership::retain`.
+  #[inline(always)]
+  pub unsafe fn retain<T: FinchClass>(handle: Handle<T>) -> Handle<T> where T::Ownership: SharedOwnership<T> {
+    T::Ownership::retain(handle)
+  }
+
+  /// Returns a handle to an object owned by another, for methods returning `&Other`.
+  #[inline(always)]
+  pub fn borrow<T: FinchClass>(value: &T) -> Handle<T> where T::Ownership: BorrowOwnership<T> {
+    T::Ownership::borrow(value)
+  }
+
+  /// Fails to compile unless views into objects of `T` may be returned to the host.
+  #[inline(always)]
+  pub fn assert_view<T: FinchClass>() where T::Ownership: ViewOwnership<T> {}
+
+  /// Returns a handle sharing the object in `arc`, for methods returning `Arc<Self>`.
+  #[inline(always)]
+  pub fn from_arc<T: FinchClass<Ownership = Shared>>(arc: Arc<T>) -> *const T {
+    Arc::into_raw(arc)
+  }
+
+  /// Returns a new reference to the object behind `handle`, for methods taking `Arc<Self>`.
+  ///
+  /// # Safety
+  ///
+  /// `handle` must be valid.
+  #[inline(always)]
+  pub unsafe fn to_arc<T: FinchClass<Ownership = Shared>>(handle: *const T) -> Arc<T> {
+    Arc::increment_strong_count(handle);
+    Arc::from_raw(handle)
+  }
+}
+
+/// Detects at compile time whether a type is `Send` or `Sync`, for the
+/// `___send` and `___sync` metadata exported for each class.
+///
+/// `Check::<T>::SEND` resolves to the inherent constant when `T: Send`,
+/// and falls back to the `NotSend` trait constant otherwise.
+#[doc(hidden)]
+pub mod thread {
+  use std::marker::PhantomData;
+
+  pub struct Check<T>(PhantomData<T>);
+
+  pub trait NotSend {
+    const SEND: bool = false;
+  }
+
+  impl<T> NotSend for Check<T> {}
+
+  impl<T: Send> Check<T> {
+    pub const SEND: bool = true;
+  }
+
+  pub trait NotSync {
+    const SYNC: bool = false;
+  }
+
+  impl<T> NotSync for Check<T> {}
+
+  impl<T: Sync> Check<T> {
+    pub const SYNC: bool = true;
+  }
+}
+
+/// Reads the naming metadata of the struct an impl is exported for, like `thread`.
+///
+/// `Class::<T>::NAMING` resolves to `FinchClass::NAMING` when `T` is exported,
+/// and falls back to the `NotExported` trait constants otherwise, so an impl for a
+/// struct which is not exported only reports the missing marker, not every check.
+#[doc(hidden)]
+pub mod exported {
+  use std::marker::PhantomData;
+
+  use super::{FinchClass, FinchValue};
+
+  pub struct Class<T>(PhantomData<T>);
+  pub struct Value<T>(PhantomData<T>);
+
+  pub trait NotExported {
+    const EXPORTED: bool = false;
+    const NAMING: &'static str = "";
+    const SYMBOLS: &'static [&'static str] = &[];
+  }
+
+  impl<T> NotExported for Class<T> {}
+  impl<T> NotExported for Value<T> {}
+
+  impl<T: FinchClass> Class<T> {
+    pub const EXPORTED: bool = true;
+    pub const NAMING: &'static str = T::NAMING;
+    pub const SYMBOLS: &'static [&'static str] = T::SYMBOLS;
+  }
+
+  impl<T: FinchValue> Value<T> {
+    pub const EXPORTED: bool = true;
+    pub const NAMING: &'static str = T::NAMING;
+    pub const SYMBOLS: &'static [&'static str] = T::SYMBOLS;
+  }
+}
+
+/// Marker for types passed across the FFI boundary by value, implemented
+/// by structs exported with `#[finch_bindgen(value)]`.
+///
+/// # Safety
+///
+/// The type must be `#[repr(C)]` (or a primitive) and contain only FFI-safe fields.
+#[diagnostic::on_unimplemented(
+  message = "finch-gen[E0004] unsupported type '{Self}'",
+  label = "cannot be passed across the FFI boundary",
+  note = "only primitives and structs exported with #[finch_bindgen(value)] can be passed by value",
+  note = "std and third-party types such as `Duration` are passed as builtins when imported by name or written with their full path",
+  note = "go to https://finch-gen.github.io/docs/errors/E0004 for more information",
+)]
+pub unsafe trait FinchValue {
+  /// Identifies the namespace, name, prefix and naming scheme of the exported symbols.
+  const NAMING: &'static str = "";
+
+  /// The symbols exported for the struct, which the methods of its impl blocks must not reuse.
+  const SYMBOLS: &'static [&'static str] = &[];
+}
+
+macro_rules! finch_value {
+  ($($ty:ty),*) => {
+    $(unsafe impl FinchValue for $ty {})*
+  };
+}
+
+finch_value!(bool, char, u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, f32, f64);
+
+unsafe impl<T> FinchValue for *const T {}
+unsafe impl<T> FinchValue for *mut T {}
+unsafe impl<T: FinchValue, const N: usize> FinchValue for [T; N] {}
+
+/// Passes a value through unchanged, checking that it can cross the FFI boundary by value.
+#[inline(always)]
+pub fn value<T: FinchValue>(value: T) -> T {
+  value
+}
+
+/// Converts a type to and from the FFI type passed in its place, used by the generated
+/// code for every type it has no builtin conversion for, such as newtypes.
+///
+/// Implemented for every `FinchValue`, which is passed as is. It can be derived with
+/// `#[derive(FinchConvert)]` for structs with a single field, passed as that field,
+/// and for enums without fields, passed as an `i32` discriminant.
+///
+/// Functions taking a type whose `FALLIBLE` is true return a `FinchResult`, so `from_ffi`
+/// can reject values such as unknown enum discriminants.
+#[diagnostic::on_unimplemented(
+  message = "finch-gen[E0004] unsupported type '{Self}'",
+  label = "cannot be passed across the FFI boundary",
+  note = "implement `FinchConvert` for it, derive it with #[derive(FinchConvert)], or export it with #[finch_bindgen(value)]",
+  note = "std and third-party types such as `Duration` are passed as builtins when imported by name or written with their full path",
+  note = "go to https://finch-gen.github.io/docs/errors/E0004 for more information",
+)]
+pub trait FinchConvert: Sized {
+  /// The type passed to and from the host, such as a `FinchValue`, `FinchString`,
+  /// `FinchBytes` or `FinchCString`.
+  type Ffi;
+
+  /// Whether `from_ffi` can fail. An error returned by a type which is not fallible
+  /// is a bug, and aborts the process.
+  const FALLIBLE: bool = false;
+
+  fn to_ffi(self) -> Self::Ffi;
+
+  fn from_ffi(ffi: Self::Ffi) -> Result<Self, FinchString>;
+}
+
+/// Selects the return type of a function taking `FinchConvert` types, which is a
+/// `FinchResult` only if the conversion of one of them is `FALLIBLE`.
+#[doc(hidden)]
+pub struct Fallible<const FALLIBLE: bool>;
+
+#[doc(hidden)]
+pub trait Outcome<T> {
+  type Output;
+
+  fn wrap(result: FinchResult<T>) -> Self::Output;
+}
+
+impl<T> Outcome<T> for Fallible<true> {
+  type Output = FinchResult<T>;
+
+  #[inline(always)]
+  fn wrap(result: FinchResult<T>) -> FinchResult<T> {
+    result
+  }
+}
+
+impl<T> Outcome<T> for Fallible<false> {
+  type Output = T;
+
+  #[inline(always)]
+  fn wrap(result: FinchResult<T>) -> T {
+    match result {
+      FinchResult::Ok(x) => x,
+      FinchResult::Err(err) => panic!("finch-gen: conversion failed for a FinchConvert type which is not FALLIBLE: {}", err),
+      FinchResult::Cancelled => unreachable!(),
+    }
+  }
+}
+
+impl<T: FinchValue> FinchConvert for T {
+  type Ffi = T;
+
+  #[inline(always)]
+  fn to_ffi(self) -> T {
+    self
+  }
+
+  #[inline(always)]
+  fn from_ffi(ffi: T) -> Result<T, FinchString> {
+    Ok(ffi)
+  }
+}
+
+/// Implemented by the marker `export_builtins!()` defines at the crate root, so
+/// `#[finch_bindgen]` can report a missing invocation.
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+  message = "finch-gen[E0031] `finch_gen::export_builtins!()` was not invoked at the crate root",
+  label = "the builtins used by this binding are not exported",
+  note = "add `finch_gen::export_builtins!();` to your lib.rs",
+  note = "go to https://finch-gen.github.io/docs/errors/E0031 for more information",
+)]
+pub trait BuiltinsExported {}
+
+impl BuiltinsExported for () {}
+
+#[doc(hidden)]
+pub const fn assert_builtins<T: BuiltinsExported>(_: &T) {}
+
+/// The marker found by `#[finch_bindgen]` when the crate root does not define one,
+/// which fails `assert_builtins`.
+#[doc(hidden)]
+pub mod missing {
+  pub struct MissingBuiltins;
+
+  #[allow(non_upper_case_globals)]
+  pub const ___finch_bindgen___export_builtins: MissingBuiltins = MissingBuiltins;
+}
+
+/// Compares two strings at compile time, used to check that an exported impl
+/// uses the same naming options as its struct.
+#[doc(hidden)]
+pub const fn str_eq(a: &str, b: &str) -> bool {
+  let (a, b) = (a.as_bytes(), b.as_bytes());
+  if a.len() != b.len() {
+    return false;
+  }
+
+  let mut i = 0;
+  while i < a.len() {
+    if a[i] != b[i] {
+      return false;
+    }
+    i += 1;
+  }
+
+  true
+}
+
+/// Whether `list` contains `s` at compile time, used to check that the methods of an
+/// exported impl do not reuse a symbol exported for its struct.
+#[doc(hidden)]
+pub const fn str_contains(list: &[&str], s: &str) -> bool {
+  let mut i = 0;
+  while i < list.len() {
+    if str_eq(list[i], s) {
+      return true;
+    }
+    i += 1;
+  }
+
+  false
+}
+
+/// Implemented for `dyn Trait` by traits exported with `#[finch_bindgen]`,
+/// naming the vtable struct the host passes in place of the trait.
+pub trait FinchCallback {
+  type VTable;
+}
+
+/// An opaque handle to a Rust iterator, advanced by the host through
+/// the `___next` function generated for the method that returned it.
+pub struct FinchIterator<T> {
+  iter: Box<dyn Iterator<Item = T>>,
+}
+
+impl<T> FinchIterator<T> {
+  /// Boxes `iter` and returns a pointer to be handed to the host.
+  ///
+  /// # Safety
+  ///
+  /// Anything borrowed by `iter` must outlive the returned handle.
+  pub unsafe fn new<'a, I: Iterator<Item = T> + 'a>(iter: I) -> *mut Self {
+    let iter: Box<dyn Iterator<Item = T> + 'a> = Box::new(iter);
+    Box::into_raw(Box::new(Self {
+      iter: std::mem::transmute::<Box<dyn Iterator<Item = T> + 'a>, Box<dyn Iterator<Item = T>>>(iter),
+    }))
+  }
+
+  /// Like `new`, but converts borrowed items such as `&str` to their owned form,
+  /// as an item outlives the call to `___next` returning it.
+  ///
+  /// # Safety
+  ///
+  /// Anything borrowed by `iter` must outlive the returned handle.
+  pub unsafe fn new_owned<'a, B: ToOwned<Owned = T> + ?Sized + 'a, I: Iterator<Item = &'a B> + 'a>(iter: I) -> *mut Self {
+    Self::new(iter.map(B::to_owned))
+  }
+}
+
+impl<T> Iterator for FinchIterator<T> {
+  type Item = T;
+
+  fn next(&mut self) -> Option<T> {
+    self.iter.next()
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.iter.size_hint()
+  }
+}
+
+/// An opaque handle to a Rust stream, advanced by the host through the `___next`
+/// or `___next_callback` functions generated for the method that returned it.
+#[cfg(feature = "async")]
+pub struct FinchStream<T> {
+  stream: std::sync::Arc<futures::lock::Mutex<futures::stream::Abortable<futures::stream::BoxStream<'static, T>>>>,
+  abort: futures::future::AbortHandle,
+}
+
+#[cfg(feature = "async")]
+impl<T: Send + 'static> FinchStream<T> {
+  /// Boxes `stream` and returns a pointer to be handed to the host.
+  ///
+  /// # Safety
+  ///
+  /// Anything borrowed by `stream` must outlive the returned handle.
+  pub unsafe fn new<'a, S: futures::stream::Stream<Item = T> + Send + 'a>(stream: S) -> *mut Self {
+    let stream: futures::stream::BoxStream<'a, T> = Box::pin(stream);
+    let stream = std::mem::transmute::<futures::stream::BoxStream<'a, T>, futures::stream::BoxStream<'static, T>>(stream);
+    let (stream, abort) = futures::stream::abortable(stream);
+    Box::into_raw(Box::new(Self {
+      stream: std::sync::Arc::new(futures::lock::Mutex::new(stream)),
+      abort,
+    }))
+  }
+
+  /// Like `new`, but converts borrowed items such as `&str` to their owned form,
+  /// as an item outlives the call to `___next` returning it.
+  ///
+  /// # Safety
+  ///
+  /// Anything borrowed by `stream` must outlive the returned handle.
+  pub unsafe fn new_owned<'a, B: ToOwned<Owned = T> + Sync + ?Sized + 'a, S: futures::stream::Stream<Item = &'a B> + Send + 'a>(stream: S) -> *mut Self {
+    Self::new(futures::stream::StreamExt::map(stream, B::to_owned))
+  }
+
+  /// Blocks until the next item is ready, returning `None` once the stream
+  /// has finished or been cancelled, or [`Reentrant`] if called from a runtime thread.
+  pub fn next(&self) -> Result<Option<T>, Reentrant> {
+    let stream = self.stream.clone();
+    try_block_on(async move {
+      futures::stream::StreamExt::next(&mut *stream.lock().await).await
+    })
+  }
+
+  /// Waits for the next item on the shared runtime, then calls `callback` with it
+  /// from a runtime thread.
+  pub fn next_with<F: FnOnce(Option<T>) + Send + 'static>(&self, callback: F) {
+    let stream = self.stream.clone();
+    runtime().handle().spawn(async move {
+      let item = futures::stream::StreamExt::next(&mut *stream.lock().await).await;
+      callback(item);
+    });
+  }
+
+  /// Stops the stream, any pending or future calls to `next` return `None`.
+  pub fn cancel(&self) {
+    self.abort.abort();
+  }
+}
+
+#[cfg(feature = "async")]
+impl<T> Drop for FinchStream<T> {
+  fn drop(&mut self) {
+    self.abort.abort();
+  }
+}
+
+/// A token which cancels every in-flight async call it was passed to.
+#[cfg(feature = "async")]
+#[derive(Default)]
+pub struct FinchCancellationToken {
+  cancelled: std::sync::atomic::AtomicBool,
+  handles: std::sync::Mutex<Vec<futures::future::AbortHandle>>,
+}
+
+#[cfg(feature = "async")]
+impl FinchCancellationToken {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn cancel(&self) {
+    self.cancelled.store(true, std::sync::atomic::Ordering::SeqCst);
+    for handle in self.handles.lock().unwrap().drain(..) {
+      handle.abort();
+    }
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.cancelled.load(std::sync::atomic::Ordering::SeqCst)
+  }
+
+  fn register(&self, handle: futures::future::AbortHandle) {
+    let mut handles = self.handles.lock().unwrap();
+    if self.is_cancelled() {
+      handle.abort();
+    } else {
+      handles.retain(|handle| !handle.is_aborted());
+      handles.push(handle);
+    }
+  }
+}
+
+/// Runs `future` to completion on the shared runtime, returning `None` if `token`
+/// is cancelled or `timeout_ms` elapses first. A `timeout_ms` of 0 waits forever.
+/// Returns [`Reentrant`] if called from a runtime thread.
+#[cfg(feature = "async")]
+pub fn block_on_cancellable<F: std::future::Future>(token: Option<&FinchCancellationToken>, timeout_ms: u64, future: F) -> Result<Option<F::Output>, Reentrant> {
+  let (handle, registration) = futures::future::AbortHandle::new_pair();
+  if let Some(token) = token {
+    token.register(handle);
+  }
+
+  let future = futures::future::Abortable::new(future, registration);
+  try_block_on(async move {
+    if timeout_ms > 0 {
+      match tokio::time::timeout(std::time::Duration::from_millis(timeout_ms), future).await {
+        Ok(Ok(output)) => Some(output),
+        _ => None,
+      }
+    } else {
+      future.await.ok()
+    }
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::panic::catch_unwind;
+  use std::time::{Duration, UNIX_EPOCH};
+
+  use super::class::{Checked, Handles, Ownership};
+  use super::{FinchMap, FinchStr16, FinchString16, FinchTimestamp};
+
+  #[test]
+  fn handles_reject_stale_handles() {
+    unsafe {
+      let stale = <Handles as Ownership<u32>>::into_handle(1);
+      assert_eq!(<Handles as Ownership<u32>>::take(stale), 1);
+
+      // The slot may be reused, but with a new generation.
+      let handle = <Handles as Ownership<u32>>::into_handle(2);
+      assert_ne!(handle, stale);
+      assert!(catch_unwind(|| *<Handles as Ownership<u32>>::get(stale)).is_err());
+
+      assert_eq!(*<Handles as Ownership<u32>>::get(handle), 2);
+      <Handles as Ownership<u32>>::drop_handle(handle);
+    }
+  }
+
+  #[test]
+  fn handles_reject_double_free() {
+    unsafe {
+      let handle = <Handles as Ownership<String>>::into_handle(String::from("finch"));
+      <Handles as Ownership<String>>::drop_handle(handle);
+      assert!(catch_unwind(|| <Handles as Ownership<String>>::drop_handle(handle)).is_err());
+    }
+  }
+
+  #[test]
+  fn handles_reject_other_types() {
+    unsafe {
+      let handle = <Handles as Ownership<u32>>::into_handle(1);
+      assert!(catch_unwind(|| *<Handles as Ownership<u64>>::get(handle)).is_err());
+      <Handles as Ownership<u32>>::drop_handle(handle);
+    }
+  }
+
+  #[test]
+  fn checked_rejects_dangling_and_mistyped_handles() {
+    unsafe {
+      let handle = <Checked as Ownership<u32>>::into_handle(1);
+      let mistyped = handle as *mut u64;
+      assert!(catch_unwind(|| *<Checked as Ownership<u64>>::get(mistyped)).is_err());
+      assert!(catch_unwind(|| *<Checked as Ownership<u32>>::get(std::ptr::null_mut())).is_err());
+
+      assert_eq!(<Checked as Ownership<u32>>::take(handle), 1);
+      assert!(catch_unwind(|| <Checked as Ownership<u32>>::take(handle)).is_err());
+    }
+  }
+
+  #[test]
+  fn map_into_entries_takes_ownership() {
+    let map = FinchMap::from_entries(vec![(1u32, String::from("one")), (2, String::from("two"))]);
+    let entries: Vec<_> = unsafe { map.into_entries() }.collect();
+    assert_eq!(entries, vec![(1, String::from("one")), (2, String::from("two"))]);
+  }
+
+  #[test]
+  fn map_into_entries_leaves_host_arrays() {
+    let mut keys = [1u32, 2];
+    let mut values = [3u32, 4];
+    let map = FinchMap { len: 2, keys: keys.as_mut_ptr(), values: values.as_mut_ptr(), vtable: std::ptr::null() };
+
+    let entries: Vec<_> = unsafe { map.into_entries() }.collect();
+    assert_eq!(entries, vec![(1, 3), (2, 4)]);
+    assert_eq!((keys, values), ([1, 2], [3, 4]));
+  }
+
+  #[test]
+  fn timestamps_before_the_epoch() {
+    let time = UNIX_EPOCH - Duration::from_nanos(1);
+    let timestamp = FinchTimestamp::from(time);
+    assert_eq!((timestamp.secs, timestamp.nanos), (-1, 999_999_999));
+    assert_eq!(timestamp.to_system_time().ok(), Some(time));
+
+    let time = UNIX_EPOCH - Duration::from_secs(2);
+    let timestamp = FinchTimestamp::from(time);
+    assert_eq!((timestamp.secs, timestamp.nanos), (-2, 0));
+    assert_eq!(timestamp.to_system_time().ok(), Some(time));
+
+    let time = UNIX_EPOCH - Duration::new(2, 250_000_000);
+    let timestamp = FinchTimestamp::from(time);
+    assert_eq!((timestamp.secs, timestamp.nanos), (-3, 750_000_000));
+    assert_eq!(timestamp.to_system_time().ok(), Some(time));
+  }
+
+  #[test]
+  fn utf16_round_trips() {
+    for string in ["", "finch", "h\u{e9}llo", "\u{1f426} bird"] {
+      let encoded = FinchString16::encode(string);
+      let copied = unsafe { FinchString16::new(encoded.ptr, encoded.len) };
+      assert_eq!(unsafe { FinchStr16 { ptr: encoded.ptr, len: encoded.len }.to_string() }.ok().as_deref(), Some(string));
+      assert_eq!(unsafe { copied.into_string() }.ok().as_deref(), Some(string));
+    }
+  }
+
+  #[test]
+  fn utf16_rejects_unpaired_surrogates() {
+    let units = [0xd800u16, 0x61];
+    let string = unsafe { FinchString16::new(units.as_ptr(), units.len()) };
+    assert!(unsafe { string.into_string() }.is_err());
+  }
 }
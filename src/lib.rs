@@ -1,3 +1,5 @@
 pub use finch_macro::*;
 
 pub mod builtin;
+
+pub use builtin::FinchConvert;
@@ -0,0 +1,53 @@
+use finch_gen::finch_bindgen;
+
+finch_gen::export_builtins!();
+
+#[finch_bindgen]
+pub struct Widget {
+  size: u32,
+}
+
+#[finch_bindgen]
+impl Widget {
+  pub fn new(size: u32) -> Self {
+    Self { size }
+  }
+
+  pub fn size(&self) -> u32 {
+    self.size
+  }
+}
+
+/// Returns the hashes of the interface symbols of `name` in this test binary,
+/// as bindings only know them from when they were generated.
+fn interface_hashes(name: &str) -> Vec<String> {
+  let binary = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+  let prefix = format!("___finch_bindgen___abi___class___{}___interface___", name);
+
+  let mut hashes: Vec<String> = binary
+    .windows(prefix.len() + 16)
+    .filter(|window| window.starts_with(prefix.as_bytes()))
+    .map(|window| String::from_utf8_lossy(&window[prefix.len()..]).into_owned())
+    .filter(|hash| hash.bytes().all(|byte| byte.is_ascii_hexdigit()))
+    .collect();
+
+  hashes.sort();
+  hashes.dedup();
+  hashes
+}
+
+#[test]
+fn abi_version_is_exported() {
+  assert_eq!(___finch_bindgen___abi___builtin___abi_version, finch_gen::builtin::ABI_VERSION);
+}
+
+#[test]
+fn struct_and_impl_export_interface_hashes() {
+  unsafe {
+    let widget = Widget::___finch_bindgen___abi___class___Widget___static___new(3);
+    assert_eq!(Widget::___finch_bindgen___abi___class___Widget___method___size(widget), 3);
+    Widget::___finch_bindgen___abi___class___Widget___drop(widget);
+  }
+
+  assert_eq!(interface_hashes("Widget").len(), 2);
+}
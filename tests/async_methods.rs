@@ -0,0 +1,119 @@
+#![cfg(feature = "async")]
+
+use finch_gen::builtin::{FinchClosure, FinchOption, FinchResult};
+use finch_gen::finch_bindgen;
+
+finch_gen::export_builtins!();
+
+#[finch_bindgen]
+pub struct Service {
+  delay_ms: u64,
+}
+
+#[finch_bindgen]
+impl Service {
+  pub fn new(delay_ms: u64) -> Self {
+    Self { delay_ms }
+  }
+
+  pub async fn ping() -> u32 {
+    1
+  }
+
+  pub async fn wait(&self) -> u64 {
+    tokio::time::delay_for(std::time::Duration::from_millis(self.delay_ms)).await;
+    self.delay_ms
+  }
+
+  pub fn count(&self, to: u64) -> impl futures::Stream<Item = u64> {
+    futures::stream::iter(0..to)
+  }
+
+  pub fn names(&self) -> impl futures::Stream<Item = &'static str> {
+    futures::stream::iter(vec!["a", "b"])
+  }
+
+  pub fn delays(&self) -> impl futures::Stream<Item = u64> + '_ {
+    futures::stream::iter(std::iter::once(self.delay_ms))
+  }
+}
+
+#[test]
+fn zero_argument_async_method() {
+  unsafe {
+    assert_eq!(Service::___finch_bindgen___async_methods___class___Service___static___ping(), 1);
+    let result = Service::___finch_bindgen___async_methods___class___Service___static___ping___cancellable(std::ptr::null(), 0);
+    assert!(matches!(result, FinchResult::Ok(1)));
+  }
+}
+
+#[test]
+fn cancellable_timeout() {
+  unsafe {
+    let service = Service::___finch_bindgen___async_methods___class___Service___static___new(1000);
+    let result = Service::___finch_bindgen___async_methods___class___Service___method___wait___cancellable(service, std::ptr::null(), 10);
+    assert!(matches!(result, FinchResult::Cancelled));
+    Service::___finch_bindgen___async_methods___class___Service___drop(service);
+  }
+}
+
+type CountStream = finch_gen::builtin::FinchStream<u64>;
+
+unsafe extern "C" fn next_from_callback(user_data: *mut std::os::raw::c_void, _: FinchOption<u64>) {
+  let (stream, sender) = &*(user_data as *const (*mut CountStream, std::sync::mpsc::Sender<bool>));
+  let next = Service::___finch_bindgen___async_methods___class___Service___stream___count___next(*stream);
+  let cancellable = Service::___finch_bindgen___async_methods___class___Service___static___ping___cancellable(std::ptr::null(), 0);
+  sender.send(matches!(next, FinchResult::Err(_)) && matches!(cancellable, FinchResult::Err(_))).unwrap();
+}
+
+#[test]
+fn reentrant_calls_return_errors() {
+  unsafe {
+    let service = Service::___finch_bindgen___async_methods___class___Service___static___new(0);
+    let stream = Service::___finch_bindgen___async_methods___class___Service___method___count(service, 2);
+    assert!(matches!(Service::___finch_bindgen___async_methods___class___Service___stream___count___next(stream), FinchResult::Ok(FinchOption::Some(0))));
+
+    let (sender, receiver) = std::sync::mpsc::channel::<bool>();
+    let user_data = Box::into_raw(Box::new((stream, sender)));
+    Service::___finch_bindgen___async_methods___class___Service___stream___count___next_callback(stream, FinchClosure {
+      callback: next_from_callback,
+      user_data: user_data as *mut std::os::raw::c_void,
+      free: None,
+    });
+    assert!(receiver.recv().unwrap());
+
+    drop(Box::from_raw(user_data));
+    Service::___finch_bindgen___async_methods___class___Service___stream___count___drop(stream);
+    Service::___finch_bindgen___async_methods___class___Service___drop(service);
+  }
+}
+
+#[test]
+fn borrowed_stream_items_are_owned() {
+  unsafe {
+    let service = Service::___finch_bindgen___async_methods___class___Service___static___new(0);
+    let stream = Service::___finch_bindgen___async_methods___class___Service___method___names(service);
+    match Service::___finch_bindgen___async_methods___class___Service___stream___names___next(stream) {
+      FinchResult::Ok(FinchOption::Some(name)) => assert_eq!(*name.string, "a"),
+      _ => panic!("expected an item"),
+    }
+
+    Service::___finch_bindgen___async_methods___class___Service___stream___names___drop(stream);
+    Service::___finch_bindgen___async_methods___class___Service___drop(service);
+  }
+}
+
+#[test]
+fn borrowing_streams_are_marked() {
+  // `delays` borrows from `service`, which must outlive the stream.
+  assert!(___finch_bindgen___async_methods___class___Service___method___delays___borrowed);
+
+  unsafe {
+    let service = Service::___finch_bindgen___async_methods___class___Service___static___new(5);
+    let stream = Service::___finch_bindgen___async_methods___class___Service___method___delays(service);
+    assert!(matches!(Service::___finch_bindgen___async_methods___class___Service___stream___delays___next(stream), FinchResult::Ok(FinchOption::Some(5))));
+
+    Service::___finch_bindgen___async_methods___class___Service___stream___delays___drop(stream);
+    Service::___finch_bindgen___async_methods___class___Service___drop(service);
+  }
+}
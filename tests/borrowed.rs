@@ -0,0 +1,96 @@
+use finch_gen::builtin::FinchStr;
+use finch_gen::finch_bindgen;
+
+finch_gen::export_builtins!();
+
+#[finch_bindgen]
+pub struct Shelf {
+  pub row: u32,
+}
+
+#[finch_bindgen]
+pub struct Library {
+  title: String,
+  ids: Vec<u32>,
+  shelf: Shelf,
+}
+
+#[finch_bindgen]
+impl Library {
+  pub fn open(row: u32) -> Self {
+    Self { title: "Stacks".to_string(), ids: vec![3, 1, 2], shelf: Shelf { row } }
+  }
+
+  pub fn title(&self) -> &str {
+    &self.title
+  }
+
+  pub fn ids(&self) -> &[u32] {
+    &self.ids
+  }
+
+  pub fn ids_mut(&mut self) -> &mut [u32] {
+    &mut self.ids
+  }
+
+  pub fn shelf(&self) -> &Shelf {
+    &self.shelf
+  }
+
+  pub fn longer<'a>(a: &'a str, b: &'a str) -> &'a str {
+    if b.len() > a.len() { b } else { a }
+  }
+}
+
+fn str(value: &str) -> FinchStr {
+  FinchStr { ptr: value.as_ptr() as *const _, len: value.len() }
+}
+
+#[test]
+fn strings_and_slices_are_views() {
+  unsafe {
+    let library = Library::___finch_bindgen___borrowed___class___Library___static___open(1);
+
+    let title = Library::___finch_bindgen___borrowed___class___Library___method___title(library);
+    assert_eq!(std::slice::from_raw_parts(title.ptr as *const u8, title.len), b"Stacks");
+
+    let ids = Library::___finch_bindgen___borrowed___class___Library___method___ids_mut(library);
+    std::slice::from_raw_parts_mut(ids.ptr, ids.len).sort_unstable();
+
+    let ids = Library::___finch_bindgen___borrowed___class___Library___method___ids(library);
+    assert_eq!(std::slice::from_raw_parts(ids.ptr, ids.len), [1, 2, 3]);
+
+    Library::___finch_bindgen___borrowed___class___Library___drop(library);
+  }
+}
+
+#[test]
+fn class_references_are_borrowed_handles() {
+  unsafe {
+    let library = Library::___finch_bindgen___borrowed___class___Library___static___open(4);
+
+    let shelf = Library::___finch_bindgen___borrowed___class___Library___method___shelf(library);
+    assert_eq!(Shelf::___finch_bindgen___borrowed___class___Shelf___getter___row(shelf), 4);
+
+    // The handle is owned by `library`, so only the library is dropped.
+    Library::___finch_bindgen___borrowed___class___Library___drop(library);
+  }
+}
+
+#[test]
+fn views_may_borrow_from_arguments() {
+  unsafe {
+    let (a, b) = ("finch", "sparrow");
+    let longer = Library::___finch_bindgen___borrowed___class___Library___static___longer(str(a), str(b));
+    assert_eq!(longer.ptr as *const u8, b.as_ptr());
+  }
+}
+
+#[test]
+fn borrowed_returns_are_marked() {
+  assert!(___finch_bindgen___borrowed___class___Library___method___title___borrowed);
+  assert!(___finch_bindgen___borrowed___class___Library___method___ids___borrowed);
+  assert!(___finch_bindgen___borrowed___class___Library___method___ids_mut___borrowed);
+  assert!(___finch_bindgen___borrowed___class___Library___method___shelf___borrowed);
+  assert!(___finch_bindgen___borrowed___class___Library___static___longer___borrowed);
+}
@@ -0,0 +1,52 @@
+use finch_gen::builtin::FinchBytes;
+
+finch_gen::export_builtins!(prefix = "mylib", naming = "snake");
+
+extern "C" {
+  static mylib_abi_version: u32;
+  fn mylib_finch_bytes_new(len: usize) -> FinchBytes;
+  fn mylib_finch_bytes_drop(value: FinchBytes);
+}
+
+#[test]
+fn named_builtins() {
+  unsafe {
+    assert_eq!(mylib_abi_version, finch_gen::builtin::ABI_VERSION);
+
+    let bytes = mylib_finch_bytes_new(5);
+    assert_eq!(bytes.len, 5);
+    mylib_finch_bytes_drop(bytes);
+  }
+}
+
+#[test]
+fn builtins_keep_their_rust_names() {
+  unsafe {
+    let string = ___finch_bindgen___mylib___builtin___FinchString___new("finch".as_ptr(), 5);
+    ___finch_bindgen___mylib___builtin___FinchString___drop(string);
+  }
+}
+
+#[test]
+fn null_c_strings_return_errors() {
+  unsafe {
+    match ___finch_bindgen___mylib___builtin___FinchCString___new(std::ptr::null()) {
+      finch_gen::builtin::FinchResult::Err(err) => assert_eq!(*err.string, "unexpected null string"),
+      _ => panic!("expected an error"),
+    }
+
+    match ___finch_bindgen___mylib___builtin___FinchCString___new(b"finch\0".as_ptr() as *const _) {
+      finch_gen::builtin::FinchResult::Ok(string) => assert_eq!(string.into_string().ok().as_deref(), Some("finch")),
+      _ => panic!("expected a string"),
+    }
+  }
+}
+
+#[test]
+fn c_strings_from_strings() {
+  use std::convert::TryFrom;
+
+  assert!(finch_gen::builtin::FinchCString::try_from(String::from("fin\0ch")).is_err());
+  let string = finch_gen::builtin::FinchCString::try_from(String::from("finch")).ok().unwrap();
+  assert_eq!(string.len, 5);
+}
@@ -0,0 +1,199 @@
+use std::os::raw::c_void;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use finch_gen::builtin::FinchClosure;
+use finch_gen::finch_bindgen;
+
+finch_gen::export_builtins!();
+
+/// Receives the totals reported by a `Counter`.
+#[finch_bindgen]
+pub trait Reporter {
+  fn report(&self, total: u32) -> u32;
+
+  fn flushed(&self) -> u32 {
+    100
+  }
+}
+
+#[finch_bindgen]
+pub struct Counter {
+  total: u32,
+  hook: Option<Box<dyn Fn(u32)>>,
+}
+
+#[finch_bindgen]
+impl Counter {
+  pub fn start(total: u32) -> Self {
+    Self { total, hook: None }
+  }
+
+  pub fn apply(&self, f: impl Fn(u32) -> u32) -> u32 {
+    f(self.total)
+  }
+
+  pub fn on_change(&mut self, hook: Box<dyn Fn(u32)>) {
+    self.hook = Some(hook);
+  }
+
+  pub fn report(&self, reporter: Box<dyn Reporter>) -> u32 {
+    reporter.report(self.total) + reporter.flushed()
+  }
+
+  pub fn add(&mut self, amount: u32) {
+    self.total += amount;
+    if let Some(hook) = &self.hook {
+      hook(self.total);
+    }
+  }
+}
+
+/// The host side of a closure, shared by its callback and `free`.
+#[derive(Default)]
+struct Host {
+  last: AtomicU32,
+  frees: AtomicUsize,
+}
+
+unsafe extern "C" fn double(user_data: *mut c_void, value: u32) -> u32 {
+  (*(user_data as *const Host)).last.store(value, Ordering::SeqCst);
+  value * 2
+}
+
+unsafe extern "C" fn record(user_data: *mut c_void, value: u32) {
+  (*(user_data as *const Host)).last.store(value, Ordering::SeqCst);
+}
+
+unsafe extern "C" fn free(user_data: *mut c_void) {
+  (*(user_data as *const Host)).frees.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test]
+fn closures_are_called_and_freed_once() {
+  let host = Host::default();
+  let user_data = &host as *const Host as *mut c_void;
+
+  unsafe {
+    let counter = Counter::___finch_bindgen___callbacks___class___Counter___static___start(0);
+    Counter::___finch_bindgen___callbacks___class___Counter___method___add(counter, 21);
+
+    let doubled = Counter::___finch_bindgen___callbacks___class___Counter___method___apply(counter, FinchClosure {
+      callback: double,
+      user_data,
+      free: Some(free),
+    });
+    assert_eq!(doubled, 42);
+    assert_eq!(host.last.load(Ordering::SeqCst), 21);
+    assert_eq!(host.frees.load(Ordering::SeqCst), 1);
+
+    Counter::___finch_bindgen___callbacks___class___Counter___drop(counter);
+  }
+
+  assert_eq!(host.frees.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn stored_closures_are_freed_with_their_owner() {
+  let host = Host::default();
+  let user_data = &host as *const Host as *mut c_void;
+
+  unsafe {
+    let counter = Counter::___finch_bindgen___callbacks___class___Counter___static___start(0);
+    Counter::___finch_bindgen___callbacks___class___Counter___method___on_change(counter, FinchClosure {
+      callback: record,
+      user_data,
+      free: Some(free),
+    });
+
+    Counter::___finch_bindgen___callbacks___class___Counter___method___add(counter, 3);
+    Counter::___finch_bindgen___callbacks___class___Counter___method___add(counter, 4);
+    assert_eq!(host.last.load(Ordering::SeqCst), 7);
+    assert_eq!(host.frees.load(Ordering::SeqCst), 0);
+
+    Counter::___finch_bindgen___callbacks___class___Counter___drop(counter);
+  }
+
+  assert_eq!(host.frees.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn closures_without_free_are_left_to_the_host() {
+  let host = Host::default();
+  let user_data = &host as *const Host as *mut c_void;
+
+  unsafe {
+    let counter = Counter::___finch_bindgen___callbacks___class___Counter___static___start(0);
+    let doubled = Counter::___finch_bindgen___callbacks___class___Counter___method___apply(counter, FinchClosure {
+      callback: double,
+      user_data,
+      free: None,
+    });
+    assert_eq!(doubled, 0);
+
+    Counter::___finch_bindgen___callbacks___class___Counter___drop(counter);
+  }
+
+  assert_eq!(host.frees.load(Ordering::SeqCst), 0);
+}
+
+type ReporterVTable = ___finch_bindgen___callbacks___callback___Reporter___vtable;
+
+unsafe extern "C" fn flushed(user_data: *mut c_void) -> u32 {
+  (*(user_data as *const Host)).last.load(Ordering::SeqCst) + 1
+}
+
+#[test]
+fn vtables_dispatch_to_host_functions() {
+  let host = Host::default();
+
+  unsafe {
+    let counter = Counter::___finch_bindgen___callbacks___class___Counter___static___start(5);
+    let reported = Counter::___finch_bindgen___callbacks___class___Counter___method___report(counter, ReporterVTable {
+      user_data: &host as *const Host as *mut c_void,
+      free: Some(free),
+      report: double,
+      flushed: Some(flushed),
+    });
+    assert_eq!(reported, 10 + 6);
+    assert_eq!(host.last.load(Ordering::SeqCst), 5);
+    assert_eq!(host.frees.load(Ordering::SeqCst), 1);
+
+    Counter::___finch_bindgen___callbacks___class___Counter___drop(counter);
+  }
+}
+
+#[test]
+fn null_vtable_fields_use_the_default_method() {
+  let host = Host::default();
+
+  unsafe {
+    let counter = Counter::___finch_bindgen___callbacks___class___Counter___static___start(5);
+    let reported = Counter::___finch_bindgen___callbacks___class___Counter___method___report(counter, ReporterVTable {
+      user_data: &host as *const Host as *mut c_void,
+      free: None,
+      report: double,
+      flushed: None,
+    });
+    assert_eq!(reported, 10 + 100);
+
+    Counter::___finch_bindgen___callbacks___class___Counter___drop(counter);
+  }
+}
+
+#[test]
+fn vtables_are_freed_once_when_dropped() {
+  let host = Host::default();
+
+  let vtable = ReporterVTable {
+    user_data: &host as *const Host as *mut c_void,
+    free: Some(free),
+    report: double,
+    flushed: None,
+  };
+  let reporter: Box<dyn Reporter> = Box::new(vtable);
+  assert_eq!(reporter.report(4), 8);
+  assert_eq!(host.frees.load(Ordering::SeqCst), 0);
+
+  drop(reporter);
+  assert_eq!(host.frees.load(Ordering::SeqCst), 1);
+}
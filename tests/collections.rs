@@ -0,0 +1,47 @@
+use std::collections::HashSet;
+
+use finch_gen::builtin::{FinchOption, FinchTuple2};
+use finch_gen::finch_bindgen;
+
+finch_gen::export_builtins!();
+
+#[finch_bindgen]
+pub struct Grid {
+  cells: HashSet<(u32, u32)>,
+}
+
+#[finch_bindgen]
+impl Grid {
+  pub fn new(cells: HashSet<(u32, u32)>) -> Self {
+    Self { cells }
+  }
+
+  pub fn cells(&self) -> HashSet<(u32, u32)> {
+    self.cells.clone()
+  }
+
+  pub fn rows(&self) -> std::collections::HashMap<Option<u32>, u32> {
+    let mut rows = std::collections::HashMap::new();
+    for (x, _) in &self.cells {
+      *rows.entry(Some(*x)).or_insert(0) += 1;
+    }
+    rows
+  }
+}
+
+#[test]
+fn tuple_and_option_keys() {
+  unsafe {
+    let cells = finch_gen::builtin::FinchSet::from_items(vec![FinchTuple2 { _0: 1, _1: 2 }, FinchTuple2 { _0: 1, _1: 3 }]);
+    let grid = Grid::___finch_bindgen___collections___class___Grid___static___new(cells);
+
+    let cells = Grid::___finch_bindgen___collections___class___Grid___method___cells(grid);
+    assert!(((*cells.vtable).contains)(&cells as *const _ as *const _, &FinchTuple2 { _0: 1u32, _1: 3u32 } as *const _ as *const _));
+
+    let rows = Grid::___finch_bindgen___collections___class___Grid___method___rows(grid);
+    let count = ((*rows.vtable).get)(&rows as *const _ as *const _, &FinchOption::Some(1u32) as *const _ as *const _) as *const u32;
+    assert_eq!(*count, 2);
+
+    Grid::___finch_bindgen___collections___class___Grid___drop(grid);
+  }
+}
@@ -0,0 +1,173 @@
+use finch_gen::builtin::{FinchResult, FinchString};
+use finch_gen::{finch_bindgen, FinchConvert};
+
+finch_gen::export_builtins!();
+
+#[derive(Clone, Copy, PartialEq, Debug, FinchConvert)]
+pub enum Currency {
+  Euro = 1,
+  Pound = 2,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, FinchConvert)]
+pub struct Id<T>(T);
+
+/// An amount of money, passed to the host as a string such as `"12.50"`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Money {
+  cents: u64,
+}
+
+impl FinchConvert for Money {
+  type Ffi = FinchString;
+  const FALLIBLE: bool = true;
+
+  fn to_ffi(self) -> FinchString {
+    FinchString::from(format!("{}.{:02}", self.cents / 100, self.cents % 100))
+  }
+
+  fn from_ffi(ffi: FinchString) -> Result<Self, FinchString> {
+    let string = unsafe { (*ffi.string).clone() };
+    let (units, cents) = string.split_once('.').unwrap_or((&string, "0"));
+    match (units.parse::<u64>(), cents.parse::<u64>()) {
+      (Ok(units), Ok(cents)) if cents < 100 => Ok(Self { cents: units * 100 + cents }),
+      _ => Err(FinchString::from(format!("invalid amount '{}'", string))),
+    }
+  }
+}
+
+#[finch_bindgen]
+pub struct Account {
+  id: Id<u64>,
+  currency: Currency,
+  balance: Money,
+}
+
+#[finch_bindgen]
+impl Account {
+  pub fn new(id: Id<u64>, currency: Currency) -> Self {
+    Self { id, currency, balance: Money { cents: 0 } }
+  }
+
+  pub fn id(&self) -> Id<u64> {
+    self.id
+  }
+
+  pub fn currency(&self) -> Currency {
+    self.currency
+  }
+
+  pub fn deposit(&mut self, amount: Money) -> Money {
+    self.balance.cents += amount.cents;
+    self.balance
+  }
+}
+
+#[test]
+fn derived_conversions() {
+  unsafe {
+    let account = match Account::___finch_bindgen___convert___class___Account___static___new(7, 2) {
+      FinchResult::Ok(account) => account,
+      _ => panic!("expected an account"),
+    };
+
+    assert_eq!(Account::___finch_bindgen___convert___class___Account___method___id(account), 7);
+    assert_eq!(Account::___finch_bindgen___convert___class___Account___method___currency(account), 2);
+
+    Account::___finch_bindgen___convert___class___Account___drop(account);
+  }
+}
+
+#[test]
+fn invalid_discriminants_return_errors() {
+  unsafe {
+    match Account::___finch_bindgen___convert___class___Account___static___new(7, 3) {
+      FinchResult::Err(err) => assert_eq!(*err.string, "invalid discriminant 3 for 'Currency'"),
+      _ => panic!("expected an error"),
+    }
+  }
+}
+
+#[test]
+fn string_conversions() {
+  unsafe {
+    let account = match Account::___finch_bindgen___convert___class___Account___static___new(7, 1) {
+      FinchResult::Ok(account) => account,
+      _ => panic!("expected an account"),
+    };
+
+    match Account::___finch_bindgen___convert___class___Account___method___deposit(account, FinchString::from(String::from("12.50"))) {
+      FinchResult::Ok(balance) => assert_eq!(*balance.string, "12.50"),
+      _ => panic!("expected a balance"),
+    }
+
+    match Account::___finch_bindgen___convert___class___Account___method___deposit(account, FinchString::from(String::from("twelve"))) {
+      FinchResult::Err(err) => assert_eq!(*err.string, "invalid amount 'twelve'"),
+      _ => panic!("expected an error"),
+    }
+
+    Account::___finch_bindgen___convert___class___Account___drop(account);
+  }
+}
+
+#[finch_bindgen(value)]
+#[derive(Clone, Copy)]
+pub struct Point {
+  pub x: i32,
+  pub y: i32,
+}
+
+#[finch_bindgen(value)]
+impl Point {
+  pub fn offset(self, other: Self) -> Self {
+    Self { x: self.x + other.x, y: self.y + other.y }
+  }
+}
+
+#[finch_bindgen]
+pub trait Listener {
+  fn moved(&self, to: Point);
+}
+
+#[finch_bindgen]
+pub struct Path {
+  points: Vec<Point>,
+}
+
+#[finch_bindgen]
+impl Path {
+  pub fn empty() -> Self {
+    Self { points: Vec::new() }
+  }
+
+  pub fn push(&mut self, point: Point) {
+    self.points.push(point);
+  }
+
+  pub fn listen(&self, listener: Box<dyn Listener>) {
+    for point in &self.points {
+      listener.moved(*point);
+    }
+  }
+
+  pub fn deposits(&self, id: Id<u64>) -> u64 {
+    id.0
+  }
+}
+
+#[test]
+fn infallible_conversions_are_not_wrapped() {
+  // Value structs, callbacks and types whose conversion is not `FALLIBLE` keep their plain signatures.
+  let _: unsafe extern "C" fn(Point, Point) -> Point = Point::___finch_bindgen___convert___value___Point___method___offset;
+  let _: unsafe extern "C" fn(*mut Path, Point) = Path::___finch_bindgen___convert___class___Path___method___push;
+  let _: unsafe extern "C" fn(*mut Path, <dyn Listener as finch_gen::builtin::FinchCallback>::VTable) = Path::___finch_bindgen___convert___class___Path___method___listen;
+  let _: unsafe extern "C" fn(*mut Path, u64) -> u64 = Path::___finch_bindgen___convert___class___Path___method___deposits;
+
+  // Enums are `FALLIBLE`, so their functions return a `FinchResult`.
+  let _: unsafe extern "C" fn(u64, i32) -> FinchResult<*mut Account> = Account::___finch_bindgen___convert___class___Account___static___new;
+
+  unsafe {
+    let sum = Point::___finch_bindgen___convert___value___Point___method___offset(Point { x: 1, y: 2 }, Point { x: 3, y: 4 });
+    assert_eq!((sum.x, sum.y), (4, 6));
+  }
+}
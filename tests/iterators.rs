@@ -0,0 +1,43 @@
+use finch_gen::builtin::FinchOption;
+use finch_gen::finch_bindgen;
+
+finch_gen::export_builtins!();
+
+#[finch_bindgen]
+pub struct Names {
+  names: Vec<String>,
+}
+
+#[finch_bindgen]
+impl Names {
+  pub fn sample() -> Self {
+    Self { names: vec!["a".into(), "b".into()] }
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = &str> + '_ {
+    self.names.iter().map(String::as_str)
+  }
+}
+
+#[test]
+fn borrowed_items_are_owned() {
+  unsafe {
+    let names = Names::___finch_bindgen___iterators___class___Names___static___sample();
+    let iter = Names::___finch_bindgen___iterators___class___Names___method___iter(names);
+
+    let mut items = Vec::new();
+    while let FinchOption::Some(item) = Names::___finch_bindgen___iterators___class___Names___iterator___iter___next(iter) {
+      items.push((*item.string).clone());
+    }
+    assert_eq!(items, ["a", "b"]);
+
+    Names::___finch_bindgen___iterators___class___Names___iterator___iter___drop(iter);
+    Names::___finch_bindgen___iterators___class___Names___drop(names);
+  }
+}
+
+#[test]
+fn borrowing_iterators_are_marked() {
+  // `iter` borrows from `names`, which must outlive the iterator.
+  assert!(___finch_bindgen___iterators___class___Names___method___iter___borrowed);
+}
@@ -0,0 +1,110 @@
+use std::os::raw::c_void;
+
+use finch_gen::finch_bindgen;
+
+finch_gen::export_builtins!();
+
+#[finch_bindgen]
+pub struct Plain {
+  pub size: u32,
+}
+
+#[finch_bindgen]
+impl Plain {
+  pub fn new(size: u32) -> Self {
+    Self { size }
+  }
+
+  pub fn doubled(&self) -> u32 {
+    self.size * 2
+  }
+}
+
+#[finch_bindgen(naming = "snake")]
+pub struct HttpClient {
+  pub size: u32,
+}
+
+#[finch_bindgen(naming = "snake")]
+impl HttpClient {
+  pub fn new(size: u32) -> Self {
+    Self { size }
+  }
+
+  #[finch_bindgen(name = "twice")]
+  pub fn doubled(&self) -> u32 {
+    self.size * 2
+  }
+}
+
+#[finch_bindgen(prefix = "lib", naming = "{prefix}__{name}__{member}")]
+pub struct Templated {
+  pub size: u32,
+}
+
+#[finch_bindgen(prefix = "lib", naming = "{prefix}__{name}__{member}")]
+impl Templated {
+  pub fn new(size: u32) -> Self {
+    Self { size }
+  }
+
+  pub fn doubled(&self) -> u32 {
+    self.size * 2
+  }
+}
+
+extern "C" {
+  fn ___finch_bindgen___naming___class___Plain___static___new(size: u32) -> *mut c_void;
+  fn ___finch_bindgen___naming___class___Plain___method___doubled(this: *mut c_void) -> u32;
+  fn ___finch_bindgen___naming___class___Plain___getter___size(this: *mut c_void) -> u32;
+  fn ___finch_bindgen___naming___class___Plain___drop(this: *mut c_void);
+
+  fn naming_http_client_new(size: u32) -> *mut c_void;
+  fn naming_http_client_twice(this: *mut c_void) -> u32;
+  fn naming_http_client_get_size(this: *mut c_void) -> u32;
+  fn naming_http_client_drop(this: *mut c_void);
+
+  fn lib__Templated__new(size: u32) -> *mut c_void;
+  fn lib__Templated__doubled(this: *mut c_void) -> u32;
+  fn lib__Templated__get_size(this: *mut c_void) -> u32;
+  fn lib__Templated__drop(this: *mut c_void);
+}
+
+#[test]
+fn finch_scheme() {
+  unsafe {
+    let plain = ___finch_bindgen___naming___class___Plain___static___new(3);
+    assert_eq!(___finch_bindgen___naming___class___Plain___method___doubled(plain), 6);
+    assert_eq!(___finch_bindgen___naming___class___Plain___getter___size(plain), 3);
+    ___finch_bindgen___naming___class___Plain___drop(plain);
+  }
+}
+
+#[test]
+fn snake_scheme() {
+  unsafe {
+    let client = naming_http_client_new(4);
+    assert_eq!(naming_http_client_twice(client), 8);
+    assert_eq!(naming_http_client_get_size(client), 4);
+    naming_http_client_drop(client);
+  }
+}
+
+#[test]
+fn template_scheme() {
+  unsafe {
+    let templated = lib__Templated__new(5);
+    assert_eq!(lib__Templated__doubled(templated), 10);
+    assert_eq!(lib__Templated__get_size(templated), 5);
+    lib__Templated__drop(templated);
+  }
+}
+
+#[test]
+fn other_schemes_keep_their_rust_names() {
+  unsafe {
+    let client = HttpClient::___finch_bindgen___naming___class___HttpClient___static___new(6);
+    assert_eq!(HttpClient::___finch_bindgen___naming___class___HttpClient___method___twice(client), 12);
+    HttpClient::___finch_bindgen___naming___class___HttpClient___drop(client);
+  }
+}
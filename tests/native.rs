@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use finch_gen::builtin::{FinchBytes, FinchDuration, FinchResult, FinchSlice, FinchTimestamp};
+use finch_gen::finch_bindgen;
+
+finch_gen::export_builtins!();
+
+mod ticks {
+  use finch_gen::FinchConvert;
+
+  /// A number of ticks, which shares its name with `std::time::Duration`.
+  #[derive(Clone, Copy, FinchConvert)]
+  pub struct Duration(pub u32);
+}
+
+#[finch_bindgen]
+pub struct Timer {
+  timeout: std::time::Duration,
+  log: PathBuf,
+}
+
+#[finch_bindgen]
+impl Timer {
+  pub fn new(timeout: std::time::Duration) -> Self {
+    Self { timeout, log: PathBuf::new() }
+  }
+
+  pub fn timeout(&self) -> ::std::time::Duration {
+    self.timeout
+  }
+
+  pub fn set_timeout(&mut self, timeout: Duration) -> Duration {
+    std::mem::replace(&mut self.timeout, timeout)
+  }
+
+  pub fn ticks(&self, ticks: ticks::Duration) -> u32 {
+    ticks.0 * self.timeout.as_secs() as u32
+  }
+
+  pub fn expires(&self, start: SystemTime) -> SystemTime {
+    start + self.timeout
+  }
+
+  pub fn set_log(&mut self, dir: &Path, name: PathBuf) -> PathBuf {
+    self.log = dir.join(name);
+    self.log.clone()
+  }
+}
+
+#[test]
+fn full_paths_are_builtins() {
+  unsafe {
+    let timer = Timer::___finch_bindgen___native___class___Timer___static___new(FinchDuration { secs: 2, nanos: 500 });
+    let timeout = Timer::___finch_bindgen___native___class___Timer___method___timeout(timer);
+    assert_eq!((timeout.secs, timeout.nanos), (2, 500));
+
+    Timer::___finch_bindgen___native___class___Timer___drop(timer);
+  }
+}
+
+#[test]
+fn imported_names_are_builtins() {
+  unsafe {
+    let timer = Timer::___finch_bindgen___native___class___Timer___static___new(FinchDuration { secs: 2, nanos: 0 });
+    let old = Timer::___finch_bindgen___native___class___Timer___method___set_timeout(timer, FinchDuration { secs: 5, nanos: 0 });
+    assert_eq!((old.secs, old.nanos), (2, 0));
+
+    let dir = b"logs";
+    let log = Timer::___finch_bindgen___native___class___Timer___method___set_log(
+      timer,
+      FinchSlice { ptr: dir.as_ptr(), len: dir.len() },
+      FinchBytes::from(b"timer.log".to_vec()),
+    );
+    assert_eq!(log.into_vec(), PathBuf::from("logs").join("timer.log").into_os_string().into_encoded_bytes());
+
+    Timer::___finch_bindgen___native___class___Timer___drop(timer);
+  }
+}
+
+#[test]
+fn other_types_use_finch_convert() {
+  unsafe {
+    let timer = Timer::___finch_bindgen___native___class___Timer___static___new(FinchDuration { secs: 3, nanos: 0 });
+    assert_eq!(Timer::___finch_bindgen___native___class___Timer___method___ticks(timer, 4), 12);
+
+    Timer::___finch_bindgen___native___class___Timer___drop(timer);
+  }
+}
+
+#[test]
+fn out_of_range_timestamps_return_errors() {
+  unsafe {
+    let timer = Timer::___finch_bindgen___native___class___Timer___static___new(FinchDuration { secs: 2, nanos: 0 });
+    match Timer::___finch_bindgen___native___class___Timer___method___expires(timer, FinchTimestamp { secs: 10, nanos: 0 }) {
+      FinchResult::Ok(expires) => assert_eq!((expires.secs, expires.nanos), (12, 0)),
+      _ => panic!("expected a timestamp"),
+    }
+
+    match Timer::___finch_bindgen___native___class___Timer___method___expires(timer, FinchTimestamp { secs: i64::MAX, nanos: 0 }) {
+      FinchResult::Err(err) => assert_eq!(*err.string, format!("timestamp {}.000000000 out of range for 'SystemTime'", i64::MAX)),
+      _ => panic!("expected an error"),
+    }
+
+    Timer::___finch_bindgen___native___class___Timer___drop(timer);
+  }
+}
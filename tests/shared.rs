@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use finch_gen::finch_bindgen;
+
+finch_gen::export_builtins!();
+
+#[finch_bindgen(shared)]
+pub struct Cache {
+  size: u32,
+}
+
+#[finch_bindgen]
+impl Cache {
+  pub fn with_size(size: u32) -> Self {
+    Self { size }
+  }
+
+  pub fn size(&self) -> u32 {
+    self.size
+  }
+
+  /// The number of handles to this cache, not counting the receiver.
+  pub fn handles(self: Arc<Self>) -> usize {
+    Arc::strong_count(&self) - 1
+  }
+
+  pub fn share(self: Arc<Self>) -> Arc<Self> {
+    self
+  }
+
+  pub fn resize(&mut self, size: u32) {
+    self.size = size;
+  }
+}
+
+#[finch_bindgen(shared_mutex)]
+pub struct Tally {
+  count: u32,
+}
+
+#[finch_bindgen]
+impl Tally {
+  pub fn zero() -> Self {
+    Self { count: 0 }
+  }
+
+  pub fn add(&mut self, amount: u32) -> u32 {
+    self.count += amount;
+    self.count
+  }
+}
+
+#[test]
+fn retain_and_release_count_handles() {
+  unsafe {
+    let cache = Cache::___finch_bindgen___shared___class___Cache___static___with_size(3);
+    assert_eq!(Cache::___finch_bindgen___shared___class___Cache___method___handles(cache), 1);
+
+    let retained = Cache::___finch_bindgen___shared___class___Cache___retain(cache);
+    assert_eq!(retained, cache);
+    assert_eq!(Cache::___finch_bindgen___shared___class___Cache___method___handles(cache), 2);
+
+    let shared = Cache::___finch_bindgen___shared___class___Cache___method___share(cache);
+    assert_eq!(Cache::___finch_bindgen___shared___class___Cache___method___size(shared), 3);
+    assert_eq!(Cache::___finch_bindgen___shared___class___Cache___method___handles(cache), 3);
+
+    Cache::___finch_bindgen___shared___class___Cache___release(retained);
+    Cache::___finch_bindgen___shared___class___Cache___release(shared);
+    assert_eq!(Cache::___finch_bindgen___shared___class___Cache___method___handles(cache), 1);
+
+    // The last handle may mutate the object.
+    Cache::___finch_bindgen___shared___class___Cache___method___resize(cache, 4);
+    assert_eq!(Cache::___finch_bindgen___shared___class___Cache___method___size(cache), 4);
+
+    Cache::___finch_bindgen___shared___class___Cache___drop(cache);
+  }
+}
+
+#[test]
+fn shared_mutex_objects_are_mutated_through_any_handle() {
+  unsafe {
+    let tally = Tally::___finch_bindgen___shared___class___Tally___static___zero();
+    let other = Tally::___finch_bindgen___shared___class___Tally___retain(tally);
+
+    assert_eq!(Tally::___finch_bindgen___shared___class___Tally___method___add(tally, 2), 2);
+    assert_eq!(Tally::___finch_bindgen___shared___class___Tally___method___add(other, 3), 5);
+
+    Tally::___finch_bindgen___shared___class___Tally___release(tally);
+    assert_eq!(Tally::___finch_bindgen___shared___class___Tally___method___add(other, 1), 6);
+    Tally::___finch_bindgen___shared___class___Tally___drop(other);
+  }
+}
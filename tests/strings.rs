@@ -0,0 +1,85 @@
+use std::convert::TryFrom;
+
+use finch_gen::builtin::{FinchCString, FinchResult};
+use finch_gen::finch_bindgen;
+
+finch_gen::export_builtins!();
+
+#[finch_bindgen]
+pub struct Notes {
+  lines: Vec<String>,
+}
+
+#[finch_bindgen]
+impl Notes {
+  pub fn sample() -> Self {
+    Self { lines: vec!["first".into(), "se\0cond".into()] }
+  }
+
+  #[finch_bindgen(string = "c")]
+  pub fn line(&self, index: u32) -> String {
+    self.lines[index as usize].clone()
+  }
+
+  #[finch_bindgen(string = "c")]
+  pub fn push(&mut self, line: String) -> u32 {
+    self.lines.push(line);
+    self.lines.len() as u32
+  }
+
+  #[finch_bindgen(string = "c_char")]
+  pub fn find(&self, line: String) -> u32 {
+    self.lines.iter().position(|other| *other == line).map_or(u32::MAX, |index| index as u32)
+  }
+}
+
+#[test]
+fn c_strings_are_returned() {
+  unsafe {
+    let notes = Notes::___finch_bindgen___strings___class___Notes___static___sample();
+    match Notes::___finch_bindgen___strings___class___Notes___method___line(notes, 0) {
+      FinchResult::Ok(line) => assert_eq!(line.into_string().ok().unwrap(), "first"),
+      _ => panic!("expected a line"),
+    }
+
+    Notes::___finch_bindgen___strings___class___Notes___drop(notes);
+  }
+}
+
+#[test]
+fn interior_nul_bytes_return_errors() {
+  unsafe {
+    let notes = Notes::___finch_bindgen___strings___class___Notes___static___sample();
+    match Notes::___finch_bindgen___strings___class___Notes___method___line(notes, 1) {
+      FinchResult::Err(err) => assert_eq!(*err.string, "nul byte found in provided data at position: 2"),
+      _ => panic!("expected an error"),
+    }
+
+    Notes::___finch_bindgen___strings___class___Notes___drop(notes);
+  }
+}
+
+#[test]
+fn c_strings_are_received() {
+  unsafe {
+    let notes = Notes::___finch_bindgen___strings___class___Notes___static___sample();
+    let line = FinchCString::try_from(String::from("third")).ok().unwrap();
+    assert!(matches!(Notes::___finch_bindgen___strings___class___Notes___method___push(notes, line), FinchResult::Ok(3)));
+    assert!(matches!(Notes::___finch_bindgen___strings___class___Notes___method___find(notes, b"third\0".as_ptr() as *const _), FinchResult::Ok(2)));
+
+    Notes::___finch_bindgen___strings___class___Notes___drop(notes);
+  }
+}
+
+#[test]
+fn null_c_chars_return_errors() {
+  unsafe {
+    let notes = Notes::___finch_bindgen___strings___class___Notes___static___sample();
+    match Notes::___finch_bindgen___strings___class___Notes___method___find(notes, std::ptr::null()) {
+      FinchResult::Err(err) => assert_eq!(*err.string, "unexpected null string"),
+      _ => panic!("expected an error"),
+    }
+
+    Notes::___finch_bindgen___strings___class___Notes___drop(notes);
+  }
+}
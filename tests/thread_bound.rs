@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use finch_gen::finch_bindgen;
+
+finch_gen::export_builtins!();
+
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+#[finch_bindgen(thread_bound)]
+pub struct Local {
+  value: u32,
+}
+
+#[finch_bindgen]
+impl Local {
+  pub fn new(value: u32) -> Self {
+    Self { value }
+  }
+
+  pub fn value(&self) -> u32 {
+    self.value
+  }
+}
+
+impl Drop for Local {
+  fn drop(&mut self) {
+    DROPPED.fetch_add(1, Ordering::SeqCst);
+  }
+}
+
+#[test]
+fn drop_from_another_thread_is_deferred() {
+  unsafe {
+    let first = Local::___finch_bindgen___thread_bound___class___Local___static___new(1);
+    let second = Local::___finch_bindgen___thread_bound___class___Local___static___new(2);
+
+    let handle = first as usize;
+    std::thread::spawn(move || {
+      Local::___finch_bindgen___thread_bound___class___Local___drop(handle as *mut _);
+    }).join().unwrap();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 0);
+
+    assert_eq!(Local::___finch_bindgen___thread_bound___class___Local___method___value(second), 2);
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 1);
+
+    Local::___finch_bindgen___thread_bound___class___Local___drop(second);
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 2);
+  }
+}
@@ -0,0 +1,109 @@
+use std::fmt;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use finch_gen::finch_bindgen;
+
+finch_gen::export_builtins!();
+
+#[finch_bindgen(display)]
+#[derive(Clone, Debug, PartialEq, Hash)]
+pub struct Tag {
+  name: String,
+}
+
+impl fmt::Display for Tag {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "#{}", self.name)
+  }
+}
+
+#[finch_bindgen]
+impl Tag {
+  pub fn named(name: &str) -> Self {
+    Self { name: name.to_string() }
+  }
+}
+
+#[finch_bindgen(shared_mutex, eq)]
+pub struct Level {
+  value: u32,
+}
+
+impl PartialEq for Level {
+  fn eq(&self, other: &Self) -> bool {
+    // Holds the locks long enough for concurrent comparisons to overlap.
+    std::thread::sleep(Duration::from_micros(50));
+    self.value == other.value
+  }
+}
+
+#[finch_bindgen]
+impl Level {
+  pub fn at(value: u32) -> Self {
+    Self { value }
+  }
+}
+
+#[test]
+fn derived_traits() {
+  unsafe {
+    let tag = Tag::___finch_bindgen___traits___class___Tag___static___named(finch_gen::builtin::FinchStr { ptr: "finch".as_ptr() as *const _, len: 5 });
+    let clone = Tag::___finch_bindgen___traits___class___Tag___clone(tag);
+    assert_ne!(clone, tag);
+
+    assert_eq!(*Tag::___finch_bindgen___traits___class___Tag___debug(tag).string, "Tag { name: \"finch\" }");
+    assert_eq!(*Tag::___finch_bindgen___traits___class___Tag___display(tag).string, "#finch");
+    assert!(Tag::___finch_bindgen___traits___class___Tag___eq(tag, clone));
+    assert_eq!(Tag::___finch_bindgen___traits___class___Tag___hash(tag), Tag::___finch_bindgen___traits___class___Tag___hash(clone));
+
+    let other = Tag::___finch_bindgen___traits___class___Tag___static___named(finch_gen::builtin::FinchStr { ptr: "bird".as_ptr() as *const _, len: 4 });
+    assert!(!Tag::___finch_bindgen___traits___class___Tag___eq(tag, other));
+    assert_ne!(Tag::___finch_bindgen___traits___class___Tag___hash(tag), Tag::___finch_bindgen___traits___class___Tag___hash(other));
+
+    Tag::___finch_bindgen___traits___class___Tag___drop(other);
+    Tag::___finch_bindgen___traits___class___Tag___drop(clone);
+    Tag::___finch_bindgen___traits___class___Tag___drop(tag);
+  }
+}
+
+#[test]
+fn locked_objects_equal_themselves() {
+  unsafe {
+    let level = Level::___finch_bindgen___traits___class___Level___static___at(1);
+    assert!(Level::___finch_bindgen___traits___class___Level___eq(level, level));
+    Level::___finch_bindgen___traits___class___Level___drop(level);
+  }
+}
+
+#[test]
+fn locked_objects_compare_in_either_order() {
+  let (a, b) = unsafe {
+    (
+      Level::___finch_bindgen___traits___class___Level___static___at(1) as usize,
+      Level::___finch_bindgen___traits___class___Level___static___at(1) as usize,
+    )
+  };
+
+  // Each thread compares the pair in a different order, which deadlocks if the
+  // objects are locked in argument order.
+  let (done, finished) = mpsc::channel();
+  for &(this, other) in &[(a, b), (b, a)] {
+    let done = done.clone();
+    std::thread::spawn(move || {
+      for _ in 0..200 {
+        assert!(unsafe { Level::___finch_bindgen___traits___class___Level___eq(this as *const _, other as *const _) });
+      }
+      done.send(()).unwrap();
+    });
+  }
+
+  for _ in 0..2 {
+    finished.recv_timeout(Duration::from_secs(10)).expect("comparisons deadlocked");
+  }
+
+  unsafe {
+    Level::___finch_bindgen___traits___class___Level___drop(a as *const _);
+    Level::___finch_bindgen___traits___class___Level___drop(b as *const _);
+  }
+}
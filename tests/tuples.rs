@@ -0,0 +1,90 @@
+use finch_gen::builtin::{FinchArray, FinchOption, FinchString, FinchTuple2, FinchTuple3};
+use finch_gen::finch_bindgen;
+
+finch_gen::export_builtins!();
+
+#[finch_bindgen]
+pub struct Grid {
+  origin: (i32, i32),
+  row: [u8; 3],
+  name: String,
+}
+
+#[finch_bindgen]
+impl Grid {
+  pub fn named(name: String) -> Self {
+    Self { origin: (0, 0), row: [0; 3], name }
+  }
+
+  pub fn move_by(&mut self, delta: (i32, i32)) -> (i32, i32) {
+    self.origin = (self.origin.0 + delta.0, self.origin.1 + delta.1);
+    self.origin
+  }
+
+  pub fn summary(&self) -> (u32, i64, bool) {
+    (self.row.iter().map(|cell| *cell as u32).sum(), self.origin.0 as i64 * self.origin.1 as i64, self.name.is_empty())
+  }
+
+  pub fn set_row(&mut self, row: [u8; 3]) -> [u8; 3] {
+    std::mem::replace(&mut self.row, row)
+  }
+
+  pub fn labels(&self) -> (String, Option<u32>) {
+    (self.name.clone(), self.row.iter().position(|cell| *cell > 0).map(|index| index as u32))
+  }
+
+  pub fn corners(&self) -> [String; 2] {
+    [format!("{}:{}", self.origin.0, self.origin.1), self.name.clone()]
+  }
+}
+
+fn string(value: &str) -> FinchString {
+  FinchString::from(value.to_string())
+}
+
+#[test]
+fn tuples_are_passed_by_value() {
+  unsafe {
+    let grid = Grid::___finch_bindgen___tuples___class___Grid___static___named(string("grid"));
+
+    let origin: FinchTuple2<i32, i32> = Grid::___finch_bindgen___tuples___class___Grid___method___move_by(grid, FinchTuple2 { _0: 2, _1: -3 });
+    assert!(origin == FinchTuple2 { _0: 2, _1: -3 });
+
+    let summary: FinchTuple3<u32, i64, bool> = Grid::___finch_bindgen___tuples___class___Grid___method___summary(grid);
+    assert!(summary == FinchTuple3 { _0: 0, _1: -6, _2: false });
+
+    Grid::___finch_bindgen___tuples___class___Grid___drop(grid);
+  }
+}
+
+#[test]
+fn arrays_are_passed_by_value() {
+  unsafe {
+    let grid = Grid::___finch_bindgen___tuples___class___Grid___static___named(string("grid"));
+
+    let old: FinchArray<u8, 3> = Grid::___finch_bindgen___tuples___class___Grid___method___set_row(grid, FinchArray { data: [1, 2, 3] });
+    assert_eq!(old.data, [0, 0, 0]);
+    assert!(Grid::___finch_bindgen___tuples___class___Grid___method___set_row(grid, FinchArray { data: [0, 4, 0] }) == FinchArray { data: [1, 2, 3] });
+
+    Grid::___finch_bindgen___tuples___class___Grid___drop(grid);
+  }
+}
+
+#[test]
+fn nested_elements_are_converted() {
+  unsafe {
+    let grid = Grid::___finch_bindgen___tuples___class___Grid___static___named(string("grid"));
+    Grid::___finch_bindgen___tuples___class___Grid___method___set_row(grid, FinchArray { data: [0, 0, 7] });
+
+    let labels = Grid::___finch_bindgen___tuples___class___Grid___method___labels(grid);
+    assert_eq!(*labels._0.string, "grid");
+    assert!(labels._1 == FinchOption::Some(2));
+
+    let corners = Grid::___finch_bindgen___tuples___class___Grid___method___corners(grid);
+    let [origin, name] = corners.data;
+    assert_eq!(*origin.string, "0:0");
+    assert_eq!(*name.string, "grid");
+
+    Grid::___finch_bindgen___tuples___class___Grid___drop(grid);
+  }
+}
@@ -0,0 +1,101 @@
+use finch_gen::builtin::{FinchOption, FinchResult};
+use finch_gen::finch_bindgen;
+
+finch_gen::export_builtins!();
+
+#[finch_bindgen(value)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Size {
+  pub width: u8,
+  pub height: u32,
+}
+
+#[finch_bindgen(value)]
+impl Size {
+  pub fn square(side: u32) -> Self {
+    Self { width: side as u8, height: side }
+  }
+
+  pub fn area(self) -> u32 {
+    self.width as u32 * self.height
+  }
+
+  pub fn max(self, other: Self) -> Self {
+    if self.area() >= other.area() { self } else { other }
+  }
+}
+
+#[finch_bindgen(value)]
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct Aligned {
+  pub value: u8,
+}
+
+#[finch_bindgen]
+pub struct Canvas {
+  size: Option<Size>,
+}
+
+#[finch_bindgen]
+impl Canvas {
+  pub fn blank() -> Self {
+    Self { size: None }
+  }
+
+  pub fn resize(&mut self, size: Option<Size>) -> Option<Size> {
+    std::mem::replace(&mut self.size, size)
+  }
+
+  pub fn checked_size(&self) -> Result<Size, String> {
+    self.size.ok_or_else(|| "no size".to_string())
+  }
+}
+
+#[test]
+fn values_are_passed_by_value() {
+  unsafe {
+    let square = Size::___finch_bindgen___values___value___Size___static___square(3);
+    assert_eq!(square, Size { width: 3, height: 3 });
+    assert_eq!(Size::___finch_bindgen___values___value___Size___method___area(square), 9);
+
+    let larger = Size::___finch_bindgen___values___value___Size___method___max(square, Size { width: 2, height: 10 });
+    assert_eq!(larger, Size { width: 2, height: 10 });
+  }
+}
+
+#[test]
+fn values_in_options_and_results() {
+  unsafe {
+    let canvas = Canvas::___finch_bindgen___values___class___Canvas___static___blank();
+
+    match Canvas::___finch_bindgen___values___class___Canvas___method___checked_size(canvas) {
+      FinchResult::Err(err) => assert_eq!(*err.string, "no size"),
+      _ => panic!("expected an error"),
+    }
+
+    let old = Canvas::___finch_bindgen___values___class___Canvas___method___resize(canvas, FinchOption::Some(Size { width: 4, height: 5 }));
+    assert!(old == FinchOption::None);
+
+    match Canvas::___finch_bindgen___values___class___Canvas___method___checked_size(canvas) {
+      FinchResult::Ok(size) => assert_eq!(size, Size { width: 4, height: 5 }),
+      _ => panic!("expected a size"),
+    }
+
+    let old = Canvas::___finch_bindgen___values___class___Canvas___method___resize(canvas, FinchOption::None);
+    assert!(old == FinchOption::Some(Size { width: 4, height: 5 }));
+
+    Canvas::___finch_bindgen___values___class___Canvas___drop(canvas);
+  }
+}
+
+#[test]
+fn layout_metadata() {
+  assert_eq!(___finch_bindgen___values___value___Size___size, 8);
+  assert_eq!(___finch_bindgen___values___value___Size___align, 4);
+  assert_eq!(___finch_bindgen___values___value___Size___offset___width, 0);
+  assert_eq!(___finch_bindgen___values___value___Size___offset___height, 4);
+
+  assert_eq!(___finch_bindgen___values___value___Aligned___size, 16);
+  assert_eq!(___finch_bindgen___values___value___Aligned___align, 16);
+}